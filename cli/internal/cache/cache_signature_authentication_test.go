@@ -137,6 +137,10 @@ func Test_EdgecaseStrings(t *testing.T) {
 		isValid, err := ffi.VerifySignature([]byte(teamID), hash, []byte(artifactBody), tag, []byte(secretKey))
 		assert.NoError(t, err)
 		assert.True(t, isValid)
+
+		rustTag, err := ffi.GenerateTag([]byte(teamID), hash, []byte(artifactBody), []byte(secretKey))
+		assert.NoError(t, err)
+		assert.Equal(t, tag, rustTag)
 	}
 }
 
@@ -162,6 +166,10 @@ func Test_RandomlyGenerateCases(t *testing.T) {
 			isValid, err := ffi.VerifySignature(teamID, hash, artifactBody, tag, secretKey)
 			assert.NoError(t, err)
 			assert.True(t, isValid)
+
+			rustTag, err := ffi.GenerateTag(teamID, hash, artifactBody, secretKey)
+			assert.NoError(t, err)
+			assert.Equal(t, tag, rustTag)
 		})
 	}
 }
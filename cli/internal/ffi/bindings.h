@@ -12,6 +12,8 @@ void free_buffer(struct Buffer buffer);
 
 struct Buffer get_turbo_data_dir(void);
 
+struct Buffer capabilities(void);
+
 struct Buffer changed_files(struct Buffer buffer);
 
 struct Buffer previous_content(struct Buffer buffer);
@@ -20,6 +22,8 @@ struct Buffer recursive_copy(struct Buffer buffer);
 
 struct Buffer verify_signature(struct Buffer buffer);
 
+struct Buffer generate_tag(struct Buffer buffer);
+
 struct Buffer transitive_closure(struct Buffer buf);
 
 struct Buffer subgraph(struct Buffer buf);
@@ -106,6 +106,35 @@ func GetTurboDataDir() string {
 	return resp.Dir
 }
 
+// ProtoSchemaVersion is the version of messages.proto this package was generated from.
+// It must match PROTO_SCHEMA_VERSION in turborepo-ffi.
+const ProtoSchemaVersion = 1
+
+// Capabilities reports the schema version and features of the linked Rust library
+func Capabilities() *ffi_proto.CapabilitiesResponse {
+	buffer := C.capabilities()
+	resp := ffi_proto.CapabilitiesResponse{}
+	if err := Unmarshal(buffer, resp.ProtoReflect().Interface()); err != nil {
+		panic(err)
+	}
+	return &resp
+}
+
+// HasFeature reports whether the linked Rust library supports the given feature,
+// and was built against the same schema version as this package
+func HasFeature(feature string) bool {
+	caps := Capabilities()
+	if caps.GetSchemaVersion() != ProtoSchemaVersion {
+		return false
+	}
+	for _, f := range caps.GetFeatures() {
+		if f == feature {
+			return true
+		}
+	}
+	return false
+}
+
 // Go convention is to use an empty string for an uninitialized or null-valued
 // string. Rust convention is to use an Option<String> for the same purpose, which
 // is encoded on the Go side as *string. This converts between the two.
@@ -338,3 +367,27 @@ func VerifySignature(teamID []byte, hash string, artifactBody []byte, expectedTa
 
 	return resp.GetVerified(), nil
 }
+
+// GenerateTag computes the signature tag of an artifact, matching the tag that VerifySignature checks
+func GenerateTag(teamID []byte, hash string, artifactBody []byte, secretKeyOverride []byte) (string, error) {
+	req := ffi_proto.GenerateTagRequest{
+		TeamId:            teamID,
+		Hash:              hash,
+		ArtifactBody:      artifactBody,
+		SecretKeyOverride: secretKeyOverride,
+	}
+	reqBuf := Marshal(&req)
+	resBuf := C.generate_tag(reqBuf)
+	reqBuf.Free()
+
+	resp := ffi_proto.GenerateTagResponse{}
+	if err := Unmarshal(resBuf, resp.ProtoReflect().Interface()); err != nil {
+		panic(err)
+	}
+
+	if err := resp.GetError(); err != "" {
+		return "", errors.New(err)
+	}
+
+	return resp.GetTag(), nil
+}
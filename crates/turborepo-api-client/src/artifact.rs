@@ -0,0 +1,104 @@
+//! Metadata that the remote cache sends and receives as headers alongside
+//! artifact bodies, rather than inside them.
+
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE};
+
+/// How long the task that produced an artifact took, in milliseconds
+pub const ARTIFACT_DURATION_HEADER: &str = "x-artifact-duration";
+/// The signature of a signed artifact, see `turborepo_cache`'s
+/// `ArtifactSignatureAuthenticator`
+pub const ARTIFACT_TAG_HEADER: &str = "x-artifact-tag";
+/// The algorithm the artifact's content hashes were computed with, named as
+/// `turborepo_cache`'s `HashAlgorithm` names them
+pub const ARTIFACT_HASH_ALGORITHM_HEADER: &str = "x-artifact-hash-algorithm";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArtifactMetadata {
+    pub duration: Option<Duration>,
+    pub tag: Option<String>,
+    pub algorithm: Option<String>,
+    pub size: Option<u64>,
+    pub content_type: Option<String>,
+}
+
+impl ArtifactMetadata {
+    /// Reads the artifact headers from a response. Headers that are missing,
+    /// empty or malformed are `None`: the body is still usable without them.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let header_str = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+
+        Self {
+            duration: header_str(ARTIFACT_DURATION_HEADER)
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_millis),
+            tag: header_str(ARTIFACT_TAG_HEADER).map(str::to_string),
+            algorithm: header_str(ARTIFACT_HASH_ALGORITHM_HEADER).map(str::to_string),
+            size: header_str(CONTENT_LENGTH.as_str()).and_then(|value| value.parse().ok()),
+            content_type: header_str(CONTENT_TYPE.as_str()).map(str::to_string),
+        }
+    }
+
+    /// The duration in whole milliseconds, as `x-artifact-duration`
+    /// carries, or 0 if it's unknown.
+    pub fn duration_millis(&self) -> u64 {
+        self.duration.map_or(0, |duration| {
+            duration.as_millis().try_into().unwrap_or(u64::MAX)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ARTIFACT_DURATION_HEADER, HeaderValue::from_static("1500"));
+        headers.insert(ARTIFACT_TAG_HEADER, HeaderValue::from_static("tag"));
+        headers.insert(
+            ARTIFACT_HASH_ALGORITHM_HEADER,
+            HeaderValue::from_static("xxhash64"),
+        );
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("42"));
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/octet-stream"),
+        );
+
+        let metadata = ArtifactMetadata::from_headers(&headers);
+        assert_eq!(
+            metadata,
+            ArtifactMetadata {
+                duration: Some(Duration::from_millis(1500)),
+                tag: Some("tag".to_string()),
+                algorithm: Some("xxhash64".to_string()),
+                size: Some(42),
+                content_type: Some("application/octet-stream".to_string()),
+            }
+        );
+        assert_eq!(metadata.duration_millis(), 1500);
+    }
+
+    #[test]
+    fn test_from_headers_tolerates_bad_values() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ARTIFACT_DURATION_HEADER, HeaderValue::from_static("-5"));
+        headers.insert(ARTIFACT_TAG_HEADER, HeaderValue::from_static(""));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("lots"));
+
+        let metadata = ArtifactMetadata::from_headers(&headers);
+        assert_eq!(metadata, ArtifactMetadata::default());
+        assert_eq!(metadata.duration_millis(), 0);
+    }
+}
@@ -0,0 +1,155 @@
+use std::env;
+
+use anyhow::{anyhow, bail, Result};
+
+const DEFAULT_API_URL: &str = "https://vercel.com/api";
+const DEFAULT_TIMEOUT: u64 = 20;
+
+const TOKEN_ENV_VARS: [&str; 2] = ["TURBO_TOKEN", "VERCEL_ARTIFACTS_TOKEN"];
+
+/// The remote cache settings turbo reads from its standard environment
+/// variables:
+///
+/// - `TURBO_API`: the API base URL, defaulting to `https://vercel.com/api`
+/// - `TURBO_TOKEN`, or `VERCEL_ARTIFACTS_TOKEN` as a fallback
+/// - `TURBO_TEAMID` and `TURBO_TEAM` (the team slug)
+/// - `TURBO_REMOTE_CACHE_TIMEOUT`: in seconds, 0 disables the timeout
+///
+/// This is for callers that only have the environment to go on, such as
+/// standalone tools. The `turbo` binary doesn't use it: its config loaders
+/// layer these same variables between command line flags and config files,
+/// and this only reads the environment layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiConfig {
+    pub base_url: String,
+    pub timeout: u64,
+    pub token: Option<String>,
+    pub team_id: Option<String>,
+    pub team_slug: Option<String>,
+}
+
+impl ApiConfig {
+    pub fn from_env() -> Result<Self> {
+        Self::from_lookup(|name| match env::var(name) {
+            Ok(value) => Ok(Some(value)),
+            Err(env::VarError::NotPresent) => Ok(None),
+            Err(env::VarError::NotUnicode(_)) => Err(anyhow!("{name} is not valid unicode")),
+        })
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Result<Option<String>>) -> Result<Self> {
+        // An empty variable is treated the same as an unset one
+        let var = |name: &str| -> Result<Option<String>> {
+            Ok(lookup(name)?.filter(|value| !value.trim().is_empty()))
+        };
+
+        let base_url = match var("TURBO_API")? {
+            Some(url) => {
+                let url = url.trim().trim_end_matches('/');
+                if !(url.starts_with("https://") || url.starts_with("http://")) {
+                    bail!("TURBO_API must be an http:// or https:// URL, got {url:?}");
+                }
+                url.to_string()
+            }
+            None => DEFAULT_API_URL.to_string(),
+        };
+
+        let timeout = match var("TURBO_REMOTE_CACHE_TIMEOUT")? {
+            Some(timeout) => timeout.trim().parse().map_err(|_| {
+                anyhow!(
+                    "TURBO_REMOTE_CACHE_TIMEOUT must be a whole number of seconds, got {timeout:?}"
+                )
+            })?,
+            None => DEFAULT_TIMEOUT,
+        };
+
+        let mut token = None;
+        for name in TOKEN_ENV_VARS {
+            if let Some(value) = var(name)? {
+                token = Some(value);
+                break;
+            }
+        }
+
+        Ok(Self {
+            base_url,
+            timeout,
+            token,
+            team_id: var("TURBO_TEAMID")?,
+            team_slug: var("TURBO_TEAM")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn load(vars: &[(&str, &str)]) -> Result<ApiConfig> {
+        let vars: HashMap<_, _> = vars.iter().copied().collect();
+        ApiConfig::from_lookup(|name| Ok(vars.get(name).map(|value| value.to_string())))
+    }
+
+    #[test]
+    fn test_defaults() -> Result<()> {
+        assert_eq!(
+            load(&[])?,
+            ApiConfig {
+                base_url: DEFAULT_API_URL.to_string(),
+                timeout: DEFAULT_TIMEOUT,
+                token: None,
+                team_id: None,
+                team_slug: None,
+            }
+        );
+        // Empty values are ignored
+        assert_eq!(
+            load(&[("TURBO_API", ""), ("TURBO_TOKEN", " ")])?,
+            load(&[])?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_all_vars() -> Result<()> {
+        let config = load(&[
+            ("TURBO_API", "http://localhost:3000/"),
+            ("TURBO_REMOTE_CACHE_TIMEOUT", "0"),
+            ("TURBO_TOKEN", "turbo-token"),
+            ("VERCEL_ARTIFACTS_TOKEN", "vercel-token"),
+            ("TURBO_TEAMID", "team_123"),
+            ("TURBO_TEAM", "my-team"),
+        ])?;
+        assert_eq!(
+            config,
+            ApiConfig {
+                base_url: "http://localhost:3000".to_string(),
+                timeout: 0,
+                token: Some("turbo-token".to_string()),
+                team_id: Some("team_123".to_string()),
+                team_slug: Some("my-team".to_string()),
+            }
+        );
+
+        let config = load(&[("VERCEL_ARTIFACTS_TOKEN", "vercel-token")])?;
+        assert_eq!(config.token.as_deref(), Some("vercel-token"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let err = load(&[("TURBO_API", "vercel.com/api")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "TURBO_API must be an http:// or https:// URL, got \"vercel.com/api\""
+        );
+
+        let err = load(&[("TURBO_REMOTE_CACHE_TIMEOUT", "-1")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "TURBO_REMOTE_CACHE_TIMEOUT must be a whole number of seconds, got \"-1\""
+        );
+    }
+}
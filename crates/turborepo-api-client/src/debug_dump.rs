@@ -0,0 +1,273 @@
+use std::{
+    collections::VecDeque,
+    env,
+    fs::{File, OpenOptions},
+    io::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::Serialize;
+use serde_json::Value;
+use tracing::warn;
+
+const REDACTED: &str = "<redacted>";
+const DEFAULT_CAPACITY: usize = 100;
+
+/// A sanitized record of a single API request and its response.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DumpEntry {
+    pub method: String,
+    pub url: String,
+    pub request_headers: Vec<(String, String)>,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub duration_ms: u128,
+    pub response_body: Option<Value>,
+}
+
+/// Records sanitized request/response metadata for debugging remote cache
+/// problems. The most recent entries are kept in memory, and every entry is
+/// also appended to a JSON lines file if one is configured.
+///
+/// Tokens are redacted from the `Authorization` header, from `token` query
+/// parameters and from any JSON body field whose name contains "token".
+#[derive(Debug)]
+pub struct DebugDump {
+    capacity: usize,
+    entries: Mutex<VecDeque<DumpEntry>>,
+    file: Option<Mutex<File>>,
+}
+
+impl DebugDump {
+    pub fn new(capacity: usize, file: Option<File>) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+            file: file.map(Mutex::new),
+        }
+    }
+
+    /// Enables the dump if `TURBO_API_DEBUG_DUMP` is set to anything but an
+    /// empty string, "0" or "false". If its value is a path, entries are
+    /// appended to that file as well.
+    pub fn from_env() -> Option<Self> {
+        let value = env::var_os("TURBO_API_DEBUG_DUMP")?;
+        if value.is_empty() || value == "0" || value.eq_ignore_ascii_case("false") {
+            return None;
+        }
+        if value == "1" || value.eq_ignore_ascii_case("true") {
+            return Some(Self::new(DEFAULT_CAPACITY, None));
+        }
+
+        let file = match OpenOptions::new().create(true).append(true).open(&value) {
+            Ok(file) => Some(file),
+            Err(err) => {
+                warn!(
+                    "failed to open API debug dump file {}, keeping the dump in memory only: {}",
+                    value.to_string_lossy(),
+                    err
+                );
+                None
+            }
+        };
+        Some(Self::new(DEFAULT_CAPACITY, file))
+    }
+
+    /// Returns the entries currently held in memory, oldest first.
+    pub fn entries(&self) -> Vec<DumpEntry> {
+        self.entries
+            .lock()
+            .expect("debug dump lock poisoned")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Builds the entry for a request, without a response body.
+    pub(crate) fn entry(
+        &self,
+        request: &reqwest::Request,
+        result: Result<reqwest::StatusCode, &reqwest::Error>,
+        duration: Duration,
+    ) -> DumpEntry {
+        let request_headers = request
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = if name == reqwest::header::AUTHORIZATION {
+                    REDACTED.to_string()
+                } else {
+                    value.to_str().unwrap_or(REDACTED).to_string()
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+
+        let (status, error) = match result {
+            Ok(status) => (Some(status.as_u16()), None),
+            Err(err) => (err.status().map(|s| s.as_u16()), Some(err.to_string())),
+        };
+
+        DumpEntry {
+            method: request.method().to_string(),
+            url: redact_url(request.url()),
+            request_headers,
+            status,
+            error,
+            duration_ms: duration.as_millis(),
+            response_body: None,
+        }
+    }
+
+    pub(crate) fn push(&self, entry: DumpEntry) {
+        if let Some(file) = &self.file {
+            // Failing to write debug output should never fail the request
+            if let Ok(mut line) = serde_json::to_vec(&entry) {
+                line.push(b'\n');
+                let _ = file
+                    .lock()
+                    .expect("debug dump lock poisoned")
+                    .write_all(&line);
+            }
+        }
+
+        let mut entries = self.entries.lock().expect("debug dump lock poisoned");
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+}
+
+/// An entry waiting for its response body to be read. It's stored in the
+/// response's extensions, and recorded when dropped, with or without a body.
+pub(crate) struct PendingEntry {
+    dump: Arc<DebugDump>,
+    entry: Option<DumpEntry>,
+}
+
+impl PendingEntry {
+    pub(crate) fn new(dump: Arc<DebugDump>, entry: DumpEntry) -> Self {
+        Self {
+            dump,
+            entry: Some(entry),
+        }
+    }
+
+    pub(crate) fn set_body(&mut self, body: &[u8]) {
+        if let Some(entry) = &mut self.entry {
+            entry.response_body = Some(redact_body(body));
+        }
+    }
+}
+
+impl Drop for PendingEntry {
+    fn drop(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            self.dump.push(entry);
+        }
+    }
+}
+
+fn redact_body(body: &[u8]) -> Value {
+    match serde_json::from_slice(body) {
+        Ok(mut value) => {
+            redact_value(&mut value);
+            value
+        }
+        Err(_) => Value::String(String::from_utf8_lossy(body).into_owned()),
+    }
+}
+
+fn redact_url(url: &reqwest::Url) -> String {
+    let mut url = url.clone();
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| {
+            let value = if key.to_lowercase().contains("token") {
+                REDACTED.to_string()
+            } else {
+                value.into_owned()
+            };
+            (key.into_owned(), value)
+        })
+        .collect();
+    if !pairs.is_empty() {
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.to_string()
+}
+
+fn redact_value(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if key.to_lowercase().contains("token") {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_value(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_value),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_redact_url() {
+        let url = reqwest::Url::parse(
+            "https://vercel.com/registration/verify?token=secret&tokenName=name&teamId=team_1",
+        )
+        .unwrap();
+        assert_eq!(
+            redact_url(&url),
+            "https://vercel.com/registration/verify?token=%3Credacted%3E&tokenName=%3Credacted%3E&\
+             teamId=team_1"
+        );
+    }
+
+    #[test]
+    fn test_redact_value() {
+        let mut value = json!({
+            "token": "secret",
+            "teamId": "team_1",
+            "nested": [{ "accessToken": "secret" }]
+        });
+        redact_value(&mut value);
+        assert_eq!(
+            value,
+            json!({
+                "token": REDACTED,
+                "teamId": "team_1",
+                "nested": [{ "accessToken": REDACTED }]
+            })
+        );
+    }
+
+    #[test]
+    fn test_ring_buffer_capacity() {
+        let dump = DebugDump::new(2, None);
+        for status in [200, 201, 202] {
+            dump.push(DumpEntry {
+                method: "GET".to_string(),
+                url: "https://vercel.com".to_string(),
+                request_headers: vec![],
+                status: Some(status),
+                error: None,
+                duration_ms: 0,
+                response_body: None,
+            });
+        }
+        let statuses: Vec<_> = dump.entries().iter().map(|entry| entry.status).collect();
+        assert_eq!(statuses, vec![Some(201), Some(202)]);
+    }
+}
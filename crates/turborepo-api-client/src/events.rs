@@ -0,0 +1,171 @@
+//! Live notifications about a team's remote cache, delivered as server-sent
+//! events from `/v8/artifacts/events`.
+
+use std::collections::VecDeque;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// A change to a team's remote cache made by someone else, e.g. another
+/// machine uploading an artifact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheEvent {
+    /// An artifact was uploaded and can now be fetched
+    ArtifactPublished { hash: String },
+    /// Artifacts were removed or should no longer be trusted. `None` means
+    /// the team's whole cache was invalidated.
+    Invalidated { hashes: Option<Vec<String>> },
+}
+
+#[derive(Deserialize)]
+struct ArtifactPublishedData {
+    hash: String,
+}
+
+#[derive(Deserialize)]
+struct InvalidatedData {
+    hashes: Option<Vec<String>>,
+}
+
+impl CacheEvent {
+    // Events with a name we don't know are skipped, so the server can add
+    // new kinds without breaking older clients
+    fn parse(name: &str, data: &str) -> Result<Option<Self>> {
+        let event = match name {
+            "artifact-published" => {
+                let data: ArtifactPublishedData = serde_json::from_str(data)
+                    .with_context(|| format!("invalid {} event: {}", name, data))?;
+                CacheEvent::ArtifactPublished { hash: data.hash }
+            }
+            "cache-invalidated" => {
+                let data: InvalidatedData = serde_json::from_str(data)
+                    .with_context(|| format!("invalid {} event: {}", name, data))?;
+                CacheEvent::Invalidated {
+                    hashes: data.hashes,
+                }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(event))
+    }
+}
+
+/// Splits a `text/event-stream` body into events as chunks of it arrive.
+/// Only the `event` and `data` fields are used.
+#[derive(Debug, Default)]
+struct EventStreamParser {
+    buffer: Vec<u8>,
+    name: Option<String>,
+    data: Vec<String>,
+}
+
+impl EventStreamParser {
+    fn feed(&mut self, chunk: &[u8], events: &mut VecDeque<CacheEvent>) -> Result<()> {
+        self.buffer.extend_from_slice(chunk);
+        while let Some(newline) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=newline).collect();
+            let line = String::from_utf8(line).context("event stream is not valid UTF-8")?;
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                let name = self.name.take();
+                let data = std::mem::take(&mut self.data);
+                if data.is_empty() {
+                    continue;
+                }
+                let name = name.as_deref().unwrap_or("message");
+                if let Some(event) = CacheEvent::parse(name, &data.join("\n"))? {
+                    events.push_back(event);
+                }
+                continue;
+            }
+
+            // Lines starting with a colon are comments, used as keep-alives
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => self.name = Some(value.to_string()),
+                "data" => self.data.push(value.to_string()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An open subscription, created with `APIClient::subscribe_cache_events`.
+pub struct CacheEventStream {
+    response: reqwest::Response,
+    parser: EventStreamParser,
+    pending: VecDeque<CacheEvent>,
+}
+
+impl CacheEventStream {
+    pub(crate) fn new(response: reqwest::Response) -> Self {
+        Self {
+            response,
+            parser: EventStreamParser::default(),
+            pending: VecDeque::with_capacity(0),
+        }
+    }
+
+    /// Waits for the next event. Returns `None` once the server closes the
+    /// stream, after which callers that want to keep listening should
+    /// subscribe again.
+    pub async fn next(&mut self) -> Result<Option<CacheEvent>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Some(event));
+            }
+            let Some(chunk) = self.response.chunk().await? else {
+                return Ok(None);
+            };
+            self.parser.feed(&chunk, &mut self.pending)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_split_chunks() -> Result<()> {
+        let stream = ": keep-alive\n\nevent: artifact-published\r\ndata: \
+                      {\"hash\":\"abc123\"}\r\n\r\nevent: team-renamed\ndata: {}\n\nevent: \
+                      cache-invalidated\ndata: {\"hashes\":\ndata: [\"def456\"]}\n\nevent: \
+                      cache-invalidated\ndata: {}\n\n";
+
+        // Every split point has to give the same events
+        for split in 0..stream.len() {
+            let mut parser = EventStreamParser::default();
+            let mut events = VecDeque::with_capacity(0);
+            parser.feed(&stream.as_bytes()[..split], &mut events)?;
+            parser.feed(&stream.as_bytes()[split..], &mut events)?;
+            assert_eq!(
+                Vec::from(events),
+                vec![
+                    CacheEvent::ArtifactPublished {
+                        hash: "abc123".to_string()
+                    },
+                    CacheEvent::Invalidated {
+                        hashes: Some(vec!["def456".to_string()])
+                    },
+                    CacheEvent::Invalidated { hashes: None },
+                ],
+                "split at {}",
+                split
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_event_data() {
+        let mut parser = EventStreamParser::default();
+        let mut events = VecDeque::with_capacity(0);
+        assert!(parser
+            .feed(b"event: artifact-published\ndata: {}\n\n", &mut events)
+            .is_err());
+    }
+}
@@ -1,17 +1,53 @@
-use std::{env, future::Future};
+use std::{
+    env,
+    ops::ControlFlow,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use anyhow::{anyhow, Result};
-use reqwest::StatusCode;
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use reqwest::{Method, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::warn;
 
-use crate::retry::retry_future;
+pub use crate::{
+    artifact::{
+        ArtifactMetadata, ARTIFACT_DURATION_HEADER, ARTIFACT_HASH_ALGORITHM_HEADER,
+        ARTIFACT_TAG_HEADER,
+    },
+    config::ApiConfig,
+    debug_dump::{DebugDump, DumpEntry},
+    events::{CacheEvent, CacheEventStream},
+    spaces::{
+        CacheSource, CacheStatus, CreateSpaceRunResponse, RunStatus, SpaceCacheStatus,
+        SpaceClientSummary, SpaceRun, SpaceTaskSummary,
+    },
+    token::{check_token_scopes, ScopeWarning, TokenMetadata, TokenResponse, TokenScope},
+};
+use crate::{
+    debug_dump::PendingEntry,
+    retry::{RequestPolicy, Retry},
+};
 
-mod retry;
+mod artifact;
+mod config;
+mod debug_dump;
+mod events;
+pub mod retry;
+mod spaces;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+mod token;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct VerifiedSsoUser {
     pub token: String,
     pub team_id: Option<String>,
+    /// Fields this client doesn't know about
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,20 +55,52 @@ pub struct VerifiedSsoUser {
 pub struct VerificationResponse {
     pub token: String,
     pub team_id: Option<String>,
+    /// Fields this client doesn't know about
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
 pub enum CachingStatus {
     Disabled,
     Enabled,
     OverLimit,
     Paused,
+    /// A status added to the API after this client was built, as sent
+    Unknown(String),
+}
+
+impl From<String> for CachingStatus {
+    fn from(status: String) -> Self {
+        match status.as_str() {
+            "disabled" => CachingStatus::Disabled,
+            "enabled" => CachingStatus::Enabled,
+            "over_limit" => CachingStatus::OverLimit,
+            "paused" => CachingStatus::Paused,
+            _ => CachingStatus::Unknown(status),
+        }
+    }
+}
+
+impl From<CachingStatus> for String {
+    fn from(status: CachingStatus) -> Self {
+        match status {
+            CachingStatus::Disabled => "disabled".to_string(),
+            CachingStatus::Enabled => "enabled".to_string(),
+            CachingStatus::OverLimit => "over_limit".to_string(),
+            CachingStatus::Paused => "paused".to_string(),
+            CachingStatus::Unknown(status) => status,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CachingStatusResponse {
     pub status: CachingStatus,
+    /// Fields this client doesn't know about
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 /// Membership is the relationship between the logged-in user and a particular
@@ -40,23 +108,57 @@ pub struct CachingStatusResponse {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Membership {
     role: Role,
+    /// Fields this client doesn't know about
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 impl Membership {
     #[allow(dead_code)]
     pub fn new(role: Role) -> Self {
-        Self { role }
+        Self {
+            role,
+            extra: Map::new(),
+        }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "UPPERCASE")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
 pub enum Role {
     Member,
     Owner,
     Viewer,
     Developer,
     Billing,
+    /// A role added to the API after this client was built, as sent
+    Unknown(String),
+}
+
+impl From<String> for Role {
+    fn from(role: String) -> Self {
+        match role.as_str() {
+            "MEMBER" => Role::Member,
+            "OWNER" => Role::Owner,
+            "VIEWER" => Role::Viewer,
+            "DEVELOPER" => Role::Developer,
+            "BILLING" => Role::Billing,
+            _ => Role::Unknown(role),
+        }
+    }
+}
+
+impl From<Role> for String {
+    fn from(role: Role) -> Self {
+        match role {
+            Role::Member => "MEMBER".to_string(),
+            Role::Owner => "OWNER".to_string(),
+            Role::Viewer => "VIEWER".to_string(),
+            Role::Developer => "DEVELOPER".to_string(),
+            Role::Billing => "BILLING".to_string(),
+            Role::Unknown(role) => role,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,8 +168,13 @@ pub struct Team {
     pub name: String,
     #[serde(rename = "createdAt")]
     pub created_at: u64,
+    #[serde(default)]
     pub created: chrono::DateTime<chrono::Utc>,
     pub membership: Membership,
+    /// Fields this client doesn't know about, kept so they survive a round
+    /// trip and can be read without a client update
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 impl Team {
@@ -76,20 +183,80 @@ impl Team {
     }
 }
 
+impl Role {
+    /// Whether members with this role can upload artifacts to the team's
+    /// remote cache. Viewers and billing members can only read.
+    pub fn can_write_artifacts(&self) -> bool {
+        matches!(self, Role::Owner | Role::Member | Role::Developer)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamMember {
+    pub uid: String,
+    pub username: String,
+    pub email: String,
+    pub name: Option<String>,
+    pub role: Role,
+    /// Whether the member has accepted their invitation
+    #[serde(default)]
+    pub confirmed: bool,
+    pub created_at: Option<u64>,
+    /// Fields this client doesn't know about
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// An invitation sent by email that hasn't been accepted yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamInvite {
+    pub id: String,
+    pub email: Option<String>,
+    pub role: Role,
+    pub created_at: Option<u64>,
+    #[serde(default)]
+    pub expired: bool,
+    /// Fields this client doesn't know about
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamMembersResponse {
+    pub members: Vec<TeamMember>,
+    #[serde(default)]
+    pub email_invite_codes: Vec<TeamInvite>,
+    /// Fields this client doesn't know about
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Space {
     pub id: String,
     pub name: String,
+    /// Fields this client doesn't know about
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TeamsResponse {
     pub teams: Vec<Team>,
+    /// Fields this client doesn't know about
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SpacesResponse {
     pub spaces: Vec<Space>,
+    /// Fields this client doesn't know about
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -100,17 +267,105 @@ pub struct User {
     pub name: Option<String>,
     #[serde(rename = "createdAt")]
     pub created_at: Option<u64>,
+    /// Fields this client doesn't know about
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserResponse {
     pub user: User,
+    /// Fields this client doesn't know about
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Headers sent with artifact downloads, for `APIClient::do_preflight`
+pub const PREFLIGHT_GET_HEADERS: &str = "Authorization, User-Agent";
+/// Headers sent with artifact uploads, for `APIClient::do_preflight`
+pub const PREFLIGHT_PUT_HEADERS: &str =
+    "Content-Type, x-artifact-duration, Authorization, User-Agent, x-artifact-tag";
+
+/// What a preflight request found out about the real request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreflightResponse {
+    /// The URL the real request should be sent to. This differs from the
+    /// original URL if the preflight was redirected.
+    pub location: reqwest::Url,
+    /// Whether the endpoint accepts an `Authorization` header. Endpoints
+    /// behind a redirect to e.g. a presigned URL reject it.
+    pub allow_authorization_header: bool,
+}
+
+/// Where `APIClient::select_team` got the team from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TeamSource {
+    /// The SSO verification response named the team
+    Verification,
+    /// The configured default team matched one of the user's teams
+    Default,
+    /// The user only belongs to one team
+    OnlyTeam,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkedTeam {
+    pub id: String,
+    /// Only known when the team was looked up from the user's teams
+    pub slug: Option<String>,
+    pub source: TeamSource,
+}
+
+/// The result of an SSO login: the verified token and, if one could be
+/// determined without asking the user, the team to link to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SsoLinkage {
+    pub token: String,
+    pub team: Option<LinkedTeam>,
+}
+
+/// Picks a team from `teams` without user input. `default_team` is matched
+/// against both team ids and slugs. Returns `None` when the choice is
+/// ambiguous, or the default team isn't one of `teams`.
+fn pick_team(teams: &[Team], default_team: Option<&str>) -> Option<LinkedTeam> {
+    let linked = |team: &Team, source| LinkedTeam {
+        id: team.id.clone(),
+        slug: Some(team.slug.clone()),
+        source,
+    };
+
+    if let Some(default_team) = default_team {
+        let team = teams
+            .iter()
+            .find(|team| team.id == default_team || team.slug == default_team);
+        if team.is_none() {
+            warn!("default team {} is not one of your teams", default_team);
+        }
+        return team.map(|team| linked(team, TeamSource::Default));
+    }
+
+    match teams {
+        [team] => Some(linked(team, TeamSource::OnlyTeam)),
+        _ => None,
+    }
+}
+
+/// The outcome of a single `APIClient::probe`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeResult {
+    /// Time from sending the request until the response headers arrived
+    pub latency: Duration,
+    /// Whether the token was accepted by the API
+    pub authenticated: bool,
 }
 
 pub struct APIClient {
     client: reqwest::Client,
     base_url: String,
     user_agent: String,
+    debug_dump: Option<Arc<DebugDump>>,
+    request_policy: RequestPolicy<reqwest::Error>,
+    use_preflight: bool,
 }
 
 impl APIClient {
@@ -118,73 +373,82 @@ impl APIClient {
         let response = self
             .make_retryable_request(|| {
                 let url = self.make_url("/v2/user");
-                let request_builder = self
-                    .client
+                self.client
                     .get(url)
                     .header("User-Agent", self.user_agent.clone())
                     .header("Authorization", format!("Bearer {}", token))
-                    .header("Content-Type", "application/json");
+                    .header("Content-Type", "application/json")
+            })
+            .await?;
 
-                request_builder.send()
+        self.read_json(response, "Error getting user").await
+    }
+
+    /// Looks up the metadata of `token` itself, including its scopes
+    pub async fn get_token_metadata(&self, token: &str) -> Result<TokenMetadata> {
+        let response = self
+            .make_retryable_request(|| {
+                self.client
+                    .get(self.make_url("/v5/user/tokens/current"))
+                    .header("User-Agent", self.user_agent.clone())
+                    .header("Authorization", format!("Bearer {}", token))
+                    .header("Content-Type", "application/json")
             })
-            .await?
-            .error_for_status()?;
+            .await?;
 
-        response.json().await.map_err(|err| {
-            anyhow!(
-                "Error getting user: {}",
-                err.status()
-                    .and_then(|status| status.canonical_reason())
-                    .unwrap_or(&err.to_string())
-            )
-        })
+        let response: TokenResponse = self.read_json(response, "Error getting token").await?;
+        Ok(response.token)
+    }
+
+    pub async fn get_token_scopes(&self, token: &str) -> Result<Vec<TokenScope>> {
+        Ok(self.get_token_metadata(token).await?.scopes)
     }
 
     pub async fn get_teams(&self, token: &str) -> Result<TeamsResponse> {
         let response = self
             .make_retryable_request(|| {
-                let request_builder = self
-                    .client
+                self.client
                     .get(self.make_url("/v2/teams?limit=100"))
                     .header("User-Agent", self.user_agent.clone())
                     .header("Content-Type", "application/json")
-                    .header("Authorization", format!("Bearer {}", token));
-
-                request_builder.send()
+                    .header("Authorization", format!("Bearer {}", token))
             })
-            .await?
-            .error_for_status()?;
+            .await?;
 
-        response.json().await.map_err(|err| {
-            anyhow!(
-                "Error getting teams: {}",
-                err.status()
-                    .and_then(|status| status.canonical_reason())
-                    .unwrap_or(&err.to_string())
-            )
-        })
+        self.read_json(response, "Error getting teams").await
     }
 
     pub async fn get_team(&self, token: &str, team_id: &str) -> Result<Option<Team>> {
-        let response = self
+        let request_builder = self
             .client
             .get(self.make_url("/v2/team"))
             .query(&[("teamId", team_id)])
             .header("User-Agent", self.user_agent.clone())
             .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", token))
-            .send()
-            .await?
-            .error_for_status()?;
+            .header("Authorization", format!("Bearer {}", token));
+        let response = self.send(request_builder).await?.error_for_status()?;
 
-        response.json().await.map_err(|err| {
-            anyhow!(
-                "Error getting team: {}",
-                err.status()
-                    .and_then(|status| status.canonical_reason())
-                    .unwrap_or(&err.to_string())
-            )
-        })
+        self.read_json(response, "Error getting team").await
+    }
+
+    /// Lists the members of a team and its pending invitations, e.g. to show
+    /// who can write to the remote cache when an upload is rejected.
+    pub async fn get_team_members(
+        &self,
+        token: &str,
+        team_id: &str,
+    ) -> Result<TeamMembersResponse> {
+        let response = self
+            .make_retryable_request(|| {
+                self.client
+                    .get(self.make_url(&format!("/v2/teams/{}/members", team_id)))
+                    .header("User-Agent", self.user_agent.clone())
+                    .header("Content-Type", "application/json")
+                    .header("Authorization", format!("Bearer {}", token))
+            })
+            .await?;
+
+        self.read_json(response, "Error getting team members").await
     }
 
     pub async fn get_caching_status(
@@ -209,19 +473,12 @@ impl APIClient {
                     request_builder = request_builder.query(&[("teamId", team_id)]);
                 }
 
-                request_builder.send()
+                request_builder
             })
-            .await?
-            .error_for_status()?;
+            .await?;
 
-        response.json().await.map_err(|err| {
-            anyhow!(
-                "Error getting caching status: {}",
-                err.status()
-                    .and_then(|status| status.canonical_reason())
-                    .unwrap_or(&err.to_string())
-            )
-        })
+        self.read_json(response, "Error getting caching status")
+            .await
     }
 
     pub async fn get_spaces(&self, token: &str, team_id: Option<&str>) -> Result<SpacesResponse> {
@@ -233,65 +490,416 @@ impl APIClient {
 
         let response = self
             .make_retryable_request(|| {
-                let request_builder = self
-                    .client
+                self.client
                     .get(self.make_url(endpoint.as_str()))
                     .header("User-Agent", self.user_agent.clone())
                     .header("Content-Type", "application/json")
-                    .header("Authorization", format!("Bearer {}", token));
-
-                request_builder.send()
+                    .header("Authorization", format!("Bearer {}", token))
             })
-            .await?
-            .error_for_status()?;
+            .await?;
 
-        response.json().await.map_err(|err| {
-            anyhow!(
-                "Error getting spaces: {}",
-                err.status()
-                    .and_then(|status| status.canonical_reason())
-                    .unwrap_or(&err.to_string())
-            )
-        })
+        self.read_json(response, "Error getting spaces").await
+    }
+
+    /// Resolves the team an SSO login should link to. A team in the
+    /// verification response is used as-is. Otherwise the user's teams are
+    /// listed, and either `default_team` (an id or slug) or the user's only
+    /// team is picked. If neither applies, or the teams can't be listed,
+    /// `SsoLinkage::team` is `None` and the caller has to ask the user.
+    pub async fn select_team(
+        &self,
+        verified_user: &VerifiedSsoUser,
+        default_team: Option<&str>,
+    ) -> SsoLinkage {
+        let team = match &verified_user.team_id {
+            Some(team_id) => Some(LinkedTeam {
+                id: team_id.clone(),
+                slug: None,
+                source: TeamSource::Verification,
+            }),
+            // The token is already valid at this point, so failing to find a
+            // team only means the user has to link one themselves
+            None => match self.get_teams(&verified_user.token).await {
+                Ok(response) => pick_team(&response.teams, default_team),
+                Err(err) => {
+                    warn!("failed to get teams: {}", err);
+                    None
+                }
+            },
+        };
+
+        SsoLinkage {
+            token: verified_user.token.clone(),
+            team,
+        }
     }
 
     pub async fn verify_sso_token(&self, token: &str, token_name: &str) -> Result<VerifiedSsoUser> {
         let response = self
             .make_retryable_request(|| {
-                let request_builder = self
-                    .client
+                self.client
                     .get(self.make_url("/registration/verify"))
                     .query(&[("token", token), ("tokenName", token_name)])
+                    .header("User-Agent", self.user_agent.clone())
+            })
+            .await?;
+
+        let verification_response: VerificationResponse =
+            self.read_json(response, "Error verifying token").await?;
+        Ok(VerifiedSsoUser {
+            token: verification_response.token,
+            team_id: verification_response.team_id,
+            extra: verification_response.extra,
+        })
+    }
+
+    /// Measures the round trip latency to the API origin and checks that
+    /// `token` is accepted. Unlike the other methods this never retries, so
+    /// that the measured latency reflects a single request.
+    pub async fn probe(
+        &self,
+        token: &str,
+        team_id: &str,
+        team_slug: Option<&str>,
+    ) -> Result<ProbeResult> {
+        let mut request_builder = self
+            .client
+            .get(self.make_url("/v8/artifacts/status"))
+            .header("User-Agent", self.user_agent.clone())
+            .header("Authorization", format!("Bearer {}", token));
+
+        if let Some(slug) = team_slug {
+            request_builder = request_builder.query(&[("teamSlug", slug)]);
+        }
+        if team_id.starts_with("team_") {
+            request_builder = request_builder.query(&[("teamId", team_id)]);
+        }
+
+        let start = Instant::now();
+        let response = self.send(request_builder).await?;
+        let latency = start.elapsed();
+
+        let authenticated = match response.status() {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => false,
+            _ => {
+                response.error_for_status()?;
+                true
+            }
+        };
+
+        Ok(ProbeResult {
+            latency,
+            authenticated,
+        })
+    }
+
+    /// Calls `probe` every `interval` and passes each result to `on_probe`,
+    /// until `on_probe` returns `ControlFlow::Break`. Intended for long-lived
+    /// processes that want to keep re-evaluating the remote cache.
+    pub async fn probe_every(
+        &self,
+        token: &str,
+        team_id: &str,
+        team_slug: Option<&str>,
+        interval: Duration,
+        mut on_probe: impl FnMut(Result<ProbeResult>) -> ControlFlow<()>,
+    ) {
+        loop {
+            if on_probe(self.probe(token, team_id, team_slug).await).is_break() {
+                return;
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Sends a CORS preflight (`OPTIONS`) request for a `request_method`
+    /// request to `request_url` that will carry `request_headers`, e.g.
+    /// `PREFLIGHT_PUT_HEADERS` for uploads. Redirects are followed, and the
+    /// returned location should be reused for the real request.
+    pub async fn do_preflight(
+        &self,
+        token: &str,
+        request_url: &str,
+        request_method: Method,
+        request_headers: &str,
+    ) -> Result<PreflightResponse> {
+        let request_builder = self
+            .client
+            .request(Method::OPTIONS, request_url)
+            .header("User-Agent", self.user_agent.clone())
+            .header("Access-Control-Request-Method", request_method.as_str())
+            .header("Access-Control-Request-Headers", request_headers)
+            .header("Authorization", format!("Bearer {}", token));
+        let response = self.send(request_builder).await?;
+
+        // A Location header on the final response points at where the real
+        // request should go. Otherwise use the URL any redirects ended at.
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| response.url().join(location).ok())
+            .unwrap_or_else(|| response.url().clone());
+        let allow_authorization_header = response
+            .headers()
+            .get(reqwest::header::ACCESS_CONTROL_ALLOW_HEADERS)
+            .and_then(|headers| headers.to_str().ok())
+            .map_or(false, |headers| {
+                headers.to_lowercase().contains("authorization")
+            });
+
+        Ok(PreflightResponse {
+            location,
+            allow_authorization_header,
+        })
+    }
+
+    /// Checks whether the remote cache has an artifact, without downloading
+    /// it.
+    pub async fn artifact_exists(
+        &self,
+        hash: &str,
+        token: &str,
+        team_id: &str,
+        team_slug: Option<&str>,
+    ) -> Result<bool> {
+        let (request_url, allow_auth) = self
+            .resolve_artifact_url(hash, token, team_id, team_slug, Method::HEAD)
+            .await
+            .context("pre-flight request failed before trying to check the HTTP cache")?;
+
+        let response = self
+            .make_retryable_request_expecting(&[StatusCode::NOT_FOUND], || {
+                let mut request_builder = self
+                    .client
+                    .head(request_url.clone())
                     .header("User-Agent", self.user_agent.clone());
+                if allow_auth {
+                    request_builder =
+                        request_builder.header("Authorization", format!("Bearer {}", token));
+                }
+                request_builder
+            })
+            .await?;
+
+        Ok(response.status() != StatusCode::NOT_FOUND)
+    }
+
+    /// Uploads an artifact to the remote cache. `duration` is how long the
+    /// task that produced it took, in milliseconds, and `tag` its signature
+    /// if artifacts are signed.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn put_artifact(
+        &self,
+        hash: &str,
+        artifact_body: impl Into<Bytes>,
+        duration: u64,
+        tag: Option<&str>,
+        token: &str,
+        team_id: &str,
+        team_slug: Option<&str>,
+    ) -> Result<()> {
+        let (request_url, allow_auth) = self
+            .resolve_artifact_url(hash, token, team_id, team_slug, Method::PUT)
+            .await
+            .context("pre-flight request failed before trying to store in HTTP cache")?;
+
+        let body = artifact_body.into();
+        let duration = duration.to_string();
+        self.make_retryable_request(|| {
+            let mut request_builder = self
+                .client
+                .put(request_url.clone())
+                .header("Content-Type", "application/octet-stream")
+                .header(ARTIFACT_DURATION_HEADER, duration.clone())
+                .header("User-Agent", self.user_agent.clone())
+                .body(body.clone());
+
+            if allow_auth {
+                request_builder =
+                    request_builder.header("Authorization", format!("Bearer {}", token));
+            }
+            if let Some(tag) = tag {
+                request_builder = request_builder.header(ARTIFACT_TAG_HEADER, tag);
+            }
+
+            request_builder
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Subscribes to changes other clients make to the team's remote cache,
+    /// so that long running processes such as watch mode can prefetch new
+    /// artifacts or drop invalidated ones.
+    ///
+    /// The stream is one long response, so a client created with a timeout
+    /// will see it end after that long.
+    pub async fn subscribe_cache_events(
+        &self,
+        token: &str,
+        team_id: &str,
+        team_slug: Option<&str>,
+    ) -> Result<CacheEventStream> {
+        let request_url = self.make_artifact_url("events", team_id, team_slug)?;
+        let response = self
+            .make_retryable_request(|| {
+                self.client
+                    .get(request_url.clone())
+                    .header("User-Agent", self.user_agent.clone())
+                    .header("Accept", "text/event-stream")
+                    .header("Authorization", format!("Bearer {}", token))
+            })
+            .await?;
+
+        Ok(CacheEventStream::new(response))
+    }
+
+    // Where an artifact request should be sent, and whether it may carry the
+    // token. Without preflight that's always the API itself.
+    async fn resolve_artifact_url(
+        &self,
+        hash: &str,
+        token: &str,
+        team_id: &str,
+        team_slug: Option<&str>,
+        method: Method,
+    ) -> Result<(reqwest::Url, bool)> {
+        let request_url = self.make_artifact_url(hash, team_id, team_slug)?;
+        if !self.use_preflight {
+            return Ok((request_url, true));
+        }
+
+        let request_headers = if method == Method::PUT {
+            PREFLIGHT_PUT_HEADERS
+        } else {
+            PREFLIGHT_GET_HEADERS
+        };
+        let preflight = self
+            .do_preflight(token, request_url.as_str(), method, request_headers)
+            .await?;
+        Ok((preflight.location, preflight.allow_authorization_header))
+    }
+
+    const RETRY_MAX: u32 = 2;
 
-                request_builder.send()
+    // Error statuses are turned into errors inside the policy, so that its
+    // layers can retry them or count them as failures.
+    async fn make_retryable_request(
+        &self,
+        request_builder: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        self.make_retryable_request_expecting(&[], request_builder)
+            .await
+    }
+
+    // Like `make_retryable_request`, but a response with one of the
+    // `expected` statuses is returned as-is instead of failing the attempt.
+    async fn make_retryable_request_expecting(
+        &self,
+        expected: &[StatusCode],
+        request_builder: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        self.request_policy
+            .run(|| async {
+                let response = self.send(request_builder()).await?;
+                if expected.contains(&response.status()) {
+                    Ok(response)
+                } else {
+                    response.error_for_status()
+                }
             })
-            .await?
-            .error_for_status()?;
+            .await
+    }
+
+    async fn send(
+        &self,
+        request_builder: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let Some(debug_dump) = &self.debug_dump else {
+            return request_builder.send().await;
+        };
+
+        let request = request_builder.build()?;
+        let dump_request = request.try_clone();
+        let start = Instant::now();
+        let result = self.client.execute(request).await;
+        let Some(dump_request) = dump_request else {
+            return result;
+        };
+
+        let entry = debug_dump.entry(
+            &dump_request,
+            result.as_ref().map(|response| response.status()),
+            start.elapsed(),
+        );
+        match result {
+            // The entry is recorded once the response is dropped, so that
+            // `read_json` can add the body to it first. Artifact bodies are
+            // never read that way, since they're large and binary.
+            Ok(mut response) => {
+                response
+                    .extensions_mut()
+                    .insert(PendingEntry::new(debug_dump.clone(), entry));
+                Ok(response)
+            }
+            Err(err) => {
+                debug_dump.push(entry);
+                Err(err)
+            }
+        }
+    }
 
-        let verification_response: VerificationResponse = response.json().await.map_err(|err| {
+    /// Deserializes a JSON response body. If the debug dump is enabled, the
+    /// body is recorded in it as well.
+    async fn read_json<T: DeserializeOwned>(
+        &self,
+        mut response: reqwest::Response,
+        context: &str,
+    ) -> Result<T> {
+        let mut pending = response.extensions_mut().remove::<PendingEntry>();
+        let body = response.bytes().await.map_err(|err| {
             anyhow!(
-                "Error verifying token: {}",
+                "{}: {}",
+                context,
                 err.status()
                     .and_then(|status| status.canonical_reason())
                     .unwrap_or(&err.to_string())
             )
         })?;
-        Ok(VerifiedSsoUser {
-            token: verification_response.token,
-            team_id: verification_response.team_id,
-        })
+        if let Some(pending) = &mut pending {
+            pending.set_body(&body);
+        }
+
+        serde_json::from_slice(&body)
+            .map_err(|err| anyhow!("{}: error decoding response body: {}", context, err))
     }
 
-    const RETRY_MAX: u32 = 2;
+    /// The API trace recorded when the debug dump is enabled through
+    /// `TURBO_API_DEBUG_DUMP` or `set_debug_dump`.
+    pub fn debug_dump(&self) -> Option<&DebugDump> {
+        self.debug_dump.as_deref()
+    }
 
-    async fn make_retryable_request<
-        F: Future<Output = Result<reqwest::Response, reqwest::Error>>,
-    >(
-        &self,
-        request_builder: impl Fn() -> F,
-    ) -> Result<reqwest::Response> {
-        retry_future(Self::RETRY_MAX, request_builder, Self::should_retry_request).await
+    pub fn set_debug_dump(&mut self, debug_dump: Option<DebugDump>) {
+        self.debug_dump = debug_dump.map(Arc::new);
+    }
+
+    /// Replaces the policy applied to retryable requests, which by default
+    /// retries server errors and rate limiting with a backoff.
+    pub fn set_request_policy(&mut self, request_policy: RequestPolicy<reqwest::Error>) {
+        self.request_policy = request_policy;
+    }
+
+    /// Sets whether artifact requests are preceded by a CORS preflight, for
+    /// remote caches that sit behind one. Off by default.
+    pub fn set_use_preflight(&mut self, use_preflight: bool) {
+        self.use_preflight = use_preflight;
+    }
+
+    pub fn default_request_policy() -> RequestPolicy<reqwest::Error> {
+        RequestPolicy::new().layer(Retry::new(Self::RETRY_MAX, Self::should_retry_request))
     }
 
     fn should_retry_request(error: &reqwest::Error) -> bool {
@@ -317,6 +925,18 @@ impl APIClient {
             reqwest::Client::builder().build()?
         };
 
+        Ok(Self::with_client(client, base_url, version))
+    }
+
+    /// Creates an API client that sends requests through `client`. This lets
+    /// callers control the transport, e.g. routing requests to a local test
+    /// harness with `ClientBuilder::resolve` or through a proxy. Timeouts are
+    /// whatever `client` was configured with.
+    pub fn with_client(
+        client: reqwest::Client,
+        base_url: impl AsRef<str>,
+        version: &'static str,
+    ) -> Self {
         let user_agent = format!(
             "turbo {} {} {} {}",
             version,
@@ -324,14 +944,459 @@ impl APIClient {
             env::consts::OS,
             env::consts::ARCH
         );
-        Ok(APIClient {
+        APIClient {
             client,
             base_url: base_url.as_ref().to_string(),
             user_agent,
-        })
+            debug_dump: DebugDump::from_env().map(Arc::new),
+            request_policy: Self::default_request_policy(),
+            use_preflight: false,
+        }
+    }
+
+    /// Creates a client from turbo's standard environment variables alone,
+    /// see `ApiConfig`. The returned config carries the token and team, which
+    /// are passed to each request rather than stored on the client.
+    ///
+    /// Nothing in this repository calls this: `turbo` builds its client from
+    /// its layered config loaders, which also read these variables.
+    pub fn from_env(version: &'static str) -> Result<(Self, ApiConfig)> {
+        let config = ApiConfig::from_env()?;
+        let client = Self::new(&config.base_url, config.timeout, version)?;
+        Ok((client, config))
     }
 
     fn make_url(&self, endpoint: &str) -> String {
         format!("{}{}", self.base_url, endpoint)
     }
+
+    fn make_artifact_url(
+        &self,
+        hash: &str,
+        team_id: &str,
+        team_slug: Option<&str>,
+    ) -> Result<reqwest::Url> {
+        let mut url = reqwest::Url::parse(&self.make_url(&format!("/v8/artifacts/{}", hash)))?;
+        if let Some(slug) = team_slug {
+            url.query_pairs_mut().append_pair("teamSlug", slug);
+        }
+        if team_id.starts_with("team_") {
+            url.query_pairs_mut().append_pair("teamId", team_id);
+        }
+        Ok(url)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{
+        retry::{CircuitBreaker, RetriesExhausted},
+        test_utils::{TestResponse, TestServer},
+    };
+
+    fn team(id: &str, slug: &str) -> Team {
+        Team {
+            id: id.to_string(),
+            slug: slug.to_string(),
+            name: slug.to_string(),
+            created_at: 0,
+            created: Default::default(),
+            membership: Membership::new(Role::Member),
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_pick_team() {
+        let teams = [team("team_1", "one"), team("team_2", "two")];
+
+        assert_eq!(pick_team(&teams, None), None);
+        assert_eq!(
+            pick_team(&teams, Some("two")),
+            Some(LinkedTeam {
+                id: "team_2".to_string(),
+                slug: Some("two".to_string()),
+                source: TeamSource::Default,
+            })
+        );
+        assert_eq!(
+            pick_team(&teams, Some("team_1")).map(|team| team.id),
+            Some("team_1".to_string())
+        );
+        assert_eq!(pick_team(&teams, Some("three")), None);
+
+        assert_eq!(
+            pick_team(&teams[..1], None).map(|team| team.source),
+            Some(TeamSource::OnlyTeam)
+        );
+        assert_eq!(pick_team(&[], None), None);
+    }
+
+    #[tokio::test]
+    async fn test_put_artifact() -> Result<()> {
+        let server = TestServer::start(|_, _| TestResponse::new("200 OK")).await;
+        let client = APIClient::new(server.url(), 10, "test")?;
+        client
+            .put_artifact(
+                "abc123",
+                Bytes::from_static(b"artifact body"),
+                1500,
+                Some("tag"),
+                "token",
+                "team_1",
+                Some("one"),
+            )
+            .await?;
+        client
+            .put_artifact("abc123", Bytes::new(), 0, None, "token", "", None)
+            .await?;
+
+        let requests = server.requests();
+        let request = &requests[0];
+        assert_eq!(request.method, "PUT");
+        assert_eq!(
+            request.path,
+            "/v8/artifacts/abc123?teamSlug=one&teamId=team_1"
+        );
+        assert_eq!(request.headers["authorization"], "Bearer token");
+        assert_eq!(request.headers["x-artifact-duration"], "1500");
+        assert_eq!(request.headers["x-artifact-tag"], "tag");
+        assert_eq!(request.headers["content-type"], "application/octet-stream");
+        assert_eq!(request.body, b"artifact body");
+
+        assert_eq!(requests[1].path, "/v8/artifacts/abc123");
+        assert_eq!(requests[1].headers["x-artifact-duration"], "0");
+        assert!(!requests[1].headers.contains_key("x-artifact-tag"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_put_artifact_preflight() -> Result<()> {
+        // The preflight sends uploads elsewhere and doesn't allow them to be
+        // authorized
+        let server = TestServer::start(|request, base_url| match request.method.as_str() {
+            "OPTIONS" => TestResponse::new("200 OK")
+                .header("location", format!("{base_url}/upload/abc123"))
+                .header("access-control-allow-headers", "Content-Type, User-Agent"),
+            _ => TestResponse::new("200 OK"),
+        })
+        .await;
+        let mut client = APIClient::new(server.url(), 10, "test")?;
+        client.set_use_preflight(true);
+        client
+            .put_artifact(
+                "abc123",
+                Bytes::from_static(b"body"),
+                0,
+                None,
+                "token",
+                "",
+                None,
+            )
+            .await?;
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "OPTIONS");
+        assert_eq!(requests[0].headers["access-control-request-method"], "PUT");
+        assert_eq!(requests[1].method, "PUT");
+        assert_eq!(requests[1].path, "/upload/abc123");
+        assert!(!requests[1].headers.contains_key("authorization"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_put_artifact_rejected() -> Result<()> {
+        let server = TestServer::start(|_, _| TestResponse::new("403 Forbidden")).await;
+        let client = APIClient::new(server.url(), 10, "test")?;
+        assert!(client
+            .put_artifact(
+                "abc123",
+                Bytes::from_static(b"body"),
+                0,
+                None,
+                "token",
+                "",
+                None,
+            )
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors() -> Result<()> {
+        let attempts = AtomicUsize::new(0);
+        let server = TestServer::start(move |_, _| match attempts.fetch_add(1, Ordering::SeqCst) {
+            0 => TestResponse::new("500 Internal Server Error"),
+            _ => TestResponse::new("200 OK"),
+        })
+        .await;
+        let client = APIClient::new(server.url(), 10, "test")?;
+        client
+            .put_artifact(
+                "abc123",
+                Bytes::from_static(b"body"),
+                0,
+                None,
+                "token",
+                "",
+                None,
+            )
+            .await?;
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].body, b"body");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retries_exhausted() -> Result<()> {
+        let server = TestServer::start(|_, _| TestResponse::new("503 Service Unavailable")).await;
+        let client = APIClient::new(server.url(), 10, "test")?;
+        let err = client.get_user("token").await.unwrap_err();
+
+        let exhausted = err
+            .downcast_ref::<RetriesExhausted>()
+            .expect("retries are exhausted");
+        let statuses: Vec<_> = exhausted
+            .attempts
+            .iter()
+            .map(|attempt| attempt.status())
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![Some(StatusCode::SERVICE_UNAVAILABLE); APIClient::RETRY_MAX as usize]
+        );
+        assert_eq!(server.requests().len(), statuses.len());
+
+        // Client errors aren't retried
+        let server = TestServer::start(|_, _| TestResponse::new("403 Forbidden")).await;
+        let client = APIClient::new(server.url(), 10, "test")?;
+        let err = client.get_user("token").await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<reqwest::Error>()
+                .and_then(|err| err.status()),
+            Some(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(server.requests().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_request_policy_sees_error_statuses() -> Result<()> {
+        let server = TestServer::start(|_, _| TestResponse::new("500 Internal Server Error")).await;
+        let mut client = APIClient::new(server.url(), 10, "test")?;
+        client.set_request_policy(
+            RequestPolicy::new().layer(CircuitBreaker::new(2, Duration::from_secs(60))),
+        );
+
+        for _ in 0..2 {
+            assert!(client.get_user("token").await.is_err());
+        }
+        // Both server errors counted as failures, so the circuit is now open
+        // and the next request isn't sent
+        let err = client.get_user("token").await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("2 consecutive requests have failed"));
+        assert_eq!(server.requests().len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_artifact_exists() -> Result<()> {
+        let server = TestServer::start(|request, _| {
+            if request.path.starts_with("/v8/artifacts/abc123") {
+                TestResponse::new("200 OK")
+            } else {
+                TestResponse::new("404 Not Found")
+            }
+        })
+        .await;
+        let client = APIClient::new(server.url(), 10, "test")?;
+        assert!(
+            client
+                .artifact_exists("abc123", "token", "team_1", None)
+                .await?
+        );
+        assert!(!client.artifact_exists("def456", "token", "", None).await?);
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "HEAD");
+        assert_eq!(requests[0].path, "/v8/artifacts/abc123?teamId=team_1");
+        assert_eq!(requests[0].headers["authorization"], "Bearer token");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_cache_events() -> Result<()> {
+        let server = TestServer::start(|_, _| {
+            let body = "event: artifact-published\ndata: {\"hash\":\"abc123\"}\n\nevent: \
+                        cache-invalidated\ndata: {}\n\n";
+            TestResponse::new("200 OK")
+                .header("content-type", "text/event-stream")
+                .body(body)
+        })
+        .await;
+        let client = APIClient::new(server.url(), 10, "test")?;
+        let mut events = client
+            .subscribe_cache_events("token", "team_1", Some("slug"))
+            .await?;
+
+        assert_eq!(
+            events.next().await?,
+            Some(CacheEvent::ArtifactPublished {
+                hash: "abc123".to_string()
+            })
+        );
+        assert_eq!(
+            events.next().await?,
+            Some(CacheEvent::Invalidated { hashes: None })
+        );
+        assert_eq!(events.next().await?, None);
+
+        let requests = server.requests();
+        assert_eq!(
+            requests[0].path,
+            "/v8/artifacts/events?teamSlug=slug&teamId=team_1"
+        );
+        assert_eq!(requests[0].headers["accept"], "text/event-stream");
+        assert_eq!(requests[0].headers["authorization"], "Bearer token");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_team_members() -> Result<()> {
+        let server = TestServer::start(|_, _| {
+            let body = serde_json::json!({
+                "members": [
+                    {
+                        "uid": "user_1",
+                        "username": "owner",
+                        "email": "owner@example.com",
+                        "role": "OWNER",
+                        "confirmed": true,
+                        "createdAt": 1680000000000u64,
+                        "avatar": "abc"
+                    },
+                    {
+                        "uid": "user_2",
+                        "username": "viewer",
+                        "email": "viewer@example.com",
+                        "name": "Viewer",
+                        "role": "VIEWER"
+                    }
+                ],
+                "emailInviteCodes": [
+                    { "id": "invite_1", "email": "new@example.com", "role": "DEVELOPER" }
+                ],
+                "pagination": { "hasNext": false }
+            })
+            .to_string();
+            TestResponse::new("200 OK").json(body)
+        })
+        .await;
+        let client = APIClient::new(server.url(), 10, "test")?;
+        let response = client.get_team_members("token", "team_1").await?;
+
+        assert_eq!(server.requests()[0].path, "/v2/teams/team_1/members");
+        let writers: Vec<_> = response
+            .members
+            .iter()
+            .filter(|member| member.role.can_write_artifacts())
+            .map(|member| member.username.as_str())
+            .collect();
+        assert_eq!(writers, vec!["owner"]);
+        assert!(response.members[0].confirmed);
+        assert!(!response.members[1].confirmed);
+        assert_eq!(response.members[0].extra["avatar"], "abc");
+        assert_eq!(response.email_invite_codes[0].id, "invite_1");
+        assert!(response.email_invite_codes[0].role.can_write_artifacts());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_debug_dump() -> Result<()> {
+        let server = TestServer::start(|request, _| {
+            if !request.path.starts_with("/v8/artifacts/status") {
+                return TestResponse::new("403 Forbidden");
+            }
+            let body = r#"{"status":"enabled","token":"secret"}"#;
+            TestResponse::new("200 OK").json(body)
+        })
+        .await;
+        let mut client = APIClient::new(server.url(), 10, "test")?;
+        client.set_debug_dump(Some(DebugDump::new(10, None)));
+
+        client.get_caching_status("token", "team_1", None).await?;
+        // The response seen by callers is the real one, including its URL
+        let err = client.get_user("token").await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains(&format!("{}/v2/user", server.url())),
+            "{}",
+            err
+        );
+
+        let entries = client.debug_dump().unwrap().entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].status, Some(200));
+        assert_eq!(
+            entries[0].response_body,
+            Some(serde_json::json!({ "status": "enabled", "token": "<redacted>" }))
+        );
+        assert!(entries[0]
+            .request_headers
+            .contains(&("authorization".to_string(), "<redacted>".to_string())));
+        assert_eq!(entries[1].status, Some(403));
+        assert_eq!(entries[1].response_body, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_fields_are_preserved() -> Result<()> {
+        let response = serde_json::json!({
+            "teams": [{
+                "id": "team_1",
+                "slug": "one",
+                "name": "One",
+                "createdAt": 0,
+                "membership": { "role": "CONTRIBUTOR", "confirmed": true },
+                "billing": { "plan": "pro" }
+            }]
+        });
+        let teams: TeamsResponse = serde_json::from_value(response)?;
+        let team = &teams.teams[0];
+        assert_eq!(
+            team.membership.role,
+            Role::Unknown("CONTRIBUTOR".to_string())
+        );
+        assert!(!team.is_owner());
+        assert_eq!(team.extra["billing"]["plan"], "pro");
+        assert!(!team.extra.contains_key("slug"));
+        assert_eq!(serde_json::to_value(team)?["billing"]["plan"], "pro");
+
+        let status: CachingStatusResponse =
+            serde_json::from_value(serde_json::json!({ "status": "migrating" }))?;
+        assert_eq!(
+            status.status,
+            CachingStatus::Unknown("migrating".to_string())
+        );
+        // Unknown values are sent back as they were received
+        assert_eq!(
+            serde_json::to_value(&status)?,
+            serde_json::json!({ "status": "migrating" })
+        );
+        assert_eq!(
+            serde_json::to_value(&team.membership)?,
+            serde_json::json!({ "role": "CONTRIBUTOR", "confirmed": true })
+        );
+        // A missing `created` defaults rather than failing the team
+        assert_eq!(team.created, chrono::DateTime::<chrono::Utc>::default());
+        Ok(())
+    }
 }
@@ -1,49 +1,489 @@
-use std::future::Future;
+//! Policies applied around each API request.
+//!
+//! A `RequestPolicy` is a chain of `Layer`s that each get a say before and
+//! after every attempt: a layer can delay an attempt (rate limiting), refuse
+//! it outright (circuit breaking), bound how long it may take (timeouts), or
+//! ask for another attempt after a failure (retries). New policies are added
+//! as layers on the client's policy instead of at each call site.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
 use anyhow::anyhow;
-use tokio::time::sleep;
+use reqwest::StatusCode;
+use tokio::time::{sleep, timeout};
 
 const MIN_SLEEP_TIME_SECS: u64 = 2;
 const MAX_SLEEP_TIME_SECS: u64 = 10;
 
-/// Retries a future until `max_retries` is reached, the `should_retry` function
-/// returns false, or the future succeeds. Uses an exponential backoff with a
-/// base of 2 to delay between retries.
-///
-/// # Arguments
-///
-/// * `max_retries`: Maximum number of retries
-/// * `future_generator`: Function to call to generate the future for each retry
-/// * `should_retry`: Determines if a retry should be attempted based on the
-///   error
+/// How a single attempt ended, as seen by `Layer::after_attempt`.
+pub enum Outcome<'a, E> {
+    Succeeded,
+    Failed(&'a E),
+    TimedOut(Duration),
+}
+
+/// What a layer wants to happen after a failed attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Try again after waiting for the given duration
+    Retry(Duration),
+    /// Return the error from this attempt as-is
+    Fail,
+    /// Stop retrying and report that too many attempts failed
+    Exhausted,
+}
+
+/// A failed attempt of a request that was eventually given up on.
+#[derive(Debug)]
+pub struct FailedAttempt {
+    /// How long the attempt took, not counting any delay before it
+    pub latency: Duration,
+    pub error: anyhow::Error,
+}
+
+impl FailedAttempt {
+    /// The HTTP status of the response, if the attempt got one
+    pub fn status(&self) -> Option<StatusCode> {
+        self.error
+            .downcast_ref::<reqwest::Error>()
+            .and_then(|err| err.status())
+    }
+}
+
+/// Returned by `RequestPolicy::run` when a layer stopped retrying with
+/// `Verdict::Exhausted`. Holds every attempt, so it's possible to tell
+/// consistent server errors apart from a mix of timeouts and failures.
+#[derive(Debug)]
+pub struct RetriesExhausted {
+    /// In the order they were made. Never empty.
+    pub attempts: Vec<FailedAttempt>,
+}
+
+impl RetriesExhausted {
+    pub fn last_error(&self) -> &anyhow::Error {
+        &self
+            .attempts
+            .last()
+            .expect("at least one attempt was made")
+            .error
+    }
+}
+
+impl fmt::Display for RetriesExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "skipping HTTP Request, too many failures have occurred.\nLast error: {}",
+            self.last_error()
+        )?;
+        for (i, attempt) in self.attempts.iter().enumerate() {
+            write!(
+                f,
+                "\n  attempt {}: {} (after {:?})",
+                i + 1,
+                attempt.error,
+                attempt.latency
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RetriesExhausted {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.last_error().as_ref())
+    }
+}
+
+pub trait Layer<E>: Send + Sync {
+    /// Runs before each attempt, starting with attempt 0. Returns how long to
+    /// wait before sending, or an error to abort the request without sending
+    /// it.
+    fn before_attempt(&self, _attempt: u32) -> anyhow::Result<Option<Duration>> {
+        Ok(None)
+    }
+
+    /// The longest a single attempt may take
+    fn attempt_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Observes the outcome of an attempt. Returning `None` means the layer
+    /// has no opinion on whether a failed attempt should be retried.
+    fn after_attempt(&self, _attempt: u32, _outcome: &Outcome<'_, E>) -> Option<Verdict> {
+        None
+    }
+}
+
+/// An ordered chain of layers applied to every attempt of a request.
 ///
-/// returns: Result<T, Error>
-pub async fn retry_future<T, E: Into<anyhow::Error>, F: Future<Output = Result<T, E>>>(
-    max_retries: u32,
-    future_generator: impl Fn() -> F,
-    should_retry: impl Fn(&E) -> bool,
-) -> Result<T, anyhow::Error> {
-    let mut last_error = None;
-    for retry_count in 0..max_retries {
-        let future = future_generator();
-        match future.await {
-            Ok(value) => return Ok(value),
-            Err(err) => {
-                if !should_retry(&err) {
-                    return Err(err.into());
+/// When layers disagree the most conservative answer wins: any error from
+/// `before_attempt` aborts, the longest delay and shortest timeout are used,
+/// and a failed attempt is only retried if no layer asks to stop.
+pub struct RequestPolicy<E> {
+    layers: Vec<Box<dyn Layer<E>>>,
+}
+
+impl<E> Default for RequestPolicy<E> {
+    fn default() -> Self {
+        Self { layers: Vec::new() }
+    }
+}
+
+impl<E> fmt::Debug for RequestPolicy<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestPolicy")
+            .field("layers", &self.layers.len())
+            .finish()
+    }
+}
+
+impl<E: Into<anyhow::Error>> RequestPolicy<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn layer(mut self, layer: impl Layer<E> + 'static) -> Self {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    /// Runs the futures produced by `future_generator` until one succeeds or
+    /// the layers decide to stop.
+    pub async fn run<T, F: Future<Output = Result<T, E>>>(
+        &self,
+        future_generator: impl Fn() -> F,
+    ) -> Result<T, anyhow::Error> {
+        let attempt_timeout = self
+            .layers
+            .iter()
+            .filter_map(|layer| layer.attempt_timeout())
+            .min();
+
+        let mut attempt = 0;
+        let mut failed_attempts = Vec::new();
+        loop {
+            let mut delay = None;
+            for layer in &self.layers {
+                delay = delay.max(layer.before_attempt(attempt)?);
+            }
+            if let Some(delay) = delay {
+                sleep(delay).await;
+            }
+
+            let start = Instant::now();
+            let result = match attempt_timeout {
+                Some(duration) => timeout(duration, future_generator())
+                    .await
+                    .map_err(|_| duration),
+                None => Ok(future_generator().await),
+            };
+            let latency = start.elapsed();
+            let outcome = match &result {
+                Ok(Ok(_)) => Outcome::Succeeded,
+                Ok(Err(err)) => Outcome::Failed(err),
+                Err(duration) => Outcome::TimedOut(*duration),
+            };
+
+            // Every layer observes the outcome, even once the verdict is known
+            let mut verdict = None;
+            for layer in &self.layers {
+                verdict = match (verdict, layer.after_attempt(attempt, &outcome)) {
+                    (verdict, None) => verdict,
+                    (None, next) => next,
+                    (Some(Verdict::Fail), _) | (_, Some(Verdict::Fail)) => Some(Verdict::Fail),
+                    (Some(Verdict::Exhausted), _) | (_, Some(Verdict::Exhausted)) => {
+                        Some(Verdict::Exhausted)
+                    }
+                    (Some(Verdict::Retry(a)), Some(Verdict::Retry(b))) => {
+                        Some(Verdict::Retry(a.max(b)))
+                    }
+                };
+            }
+
+            let err = match result {
+                Ok(Ok(value)) => return Ok(value),
+                Ok(Err(err)) => err.into(),
+                Err(duration) => anyhow!("request timed out after {:?}", duration),
+            };
+            match verdict {
+                Some(Verdict::Retry(delay)) => {
+                    failed_attempts.push(FailedAttempt {
+                        latency,
+                        error: err,
+                    });
+                    sleep(delay).await
                 }
-                last_error = Some(err);
+                Some(Verdict::Exhausted) => {
+                    failed_attempts.push(FailedAttempt {
+                        latency,
+                        error: err,
+                    });
+                    return Err(RetriesExhausted {
+                        attempts: failed_attempts,
+                    }
+                    .into());
+                }
+                Some(Verdict::Fail) | None => return Err(err),
             }
+            attempt += 1;
         }
+    }
+}
+
+/// Retries failed attempts that `should_retry` accepts, up to `max_retries`
+/// attempts in total, with an exponential backoff with a base of 2 between
+/// them. Timed out attempts are always retried.
+pub struct Retry<F> {
+    max_retries: u32,
+    should_retry: F,
+}
 
-        let sleep_period = (2_u64)
-            .pow(retry_count)
-            .clamp(MIN_SLEEP_TIME_SECS, MAX_SLEEP_TIME_SECS);
-        sleep(std::time::Duration::from_secs(sleep_period)).await;
+impl<F> Retry<F> {
+    pub fn new(max_retries: u32, should_retry: F) -> Self {
+        Self {
+            max_retries,
+            should_retry,
+        }
     }
+}
 
-    Err(anyhow!(
-        "skipping HTTP Request, too many failures have occurred.\nLast error: {}",
-        last_error.unwrap().into()
-    ))
+impl<E, F: Fn(&E) -> bool + Send + Sync> Layer<E> for Retry<F> {
+    fn after_attempt(&self, attempt: u32, outcome: &Outcome<'_, E>) -> Option<Verdict> {
+        match outcome {
+            Outcome::Succeeded => None,
+            Outcome::Failed(err) if !(self.should_retry)(err) => Some(Verdict::Fail),
+            _ if attempt + 1 >= self.max_retries => Some(Verdict::Exhausted),
+            _ => {
+                let sleep_period = (2_u64)
+                    .pow(attempt)
+                    .clamp(MIN_SLEEP_TIME_SECS, MAX_SLEEP_TIME_SECS);
+                Some(Verdict::Retry(Duration::from_secs(sleep_period)))
+            }
+        }
+    }
+}
+
+/// Gives up on an attempt that takes longer than the given duration.
+pub struct Timeout(pub Duration);
+
+impl<E> Layer<E> for Timeout {
+    fn attempt_timeout(&self) -> Option<Duration> {
+        Some(self.0)
+    }
+}
+
+/// Spaces attempts at least `min_interval` apart. Shared by every request
+/// that goes through the same policy.
+pub struct RateLimit {
+    min_interval: Duration,
+    next_slot: Mutex<Option<Instant>>,
+}
+
+impl RateLimit {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            next_slot: Mutex::new(None),
+        }
+    }
+}
+
+impl<E> Layer<E> for RateLimit {
+    fn before_attempt(&self, _attempt: u32) -> anyhow::Result<Option<Duration>> {
+        let mut next_slot = self.next_slot.lock().expect("rate limit lock poisoned");
+        let now = Instant::now();
+        let slot = next_slot.map_or(now, |slot| slot.max(now));
+        *next_slot = Some(slot + self.min_interval);
+        Ok(Some(slot - now).filter(|delay| !delay.is_zero()))
+    }
+}
+
+/// Stops sending requests after `threshold` consecutive failed attempts, and
+/// lets a single attempt through again once `cooldown` has passed.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<CircuitState>,
+}
+
+#[derive(Default)]
+struct CircuitState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            state: Mutex::default(),
+        }
+    }
+}
+
+impl<E> Layer<E> for CircuitBreaker {
+    fn before_attempt(&self, _attempt: u32) -> anyhow::Result<Option<Duration>> {
+        let mut state = self.state.lock().expect("circuit breaker lock poisoned");
+        if let Some(opened_at) = state.opened_at {
+            if opened_at.elapsed() < self.cooldown {
+                return Err(anyhow!(
+                    "skipping HTTP Request, {} consecutive requests have failed",
+                    state.consecutive_failures
+                ));
+            }
+            // Half open: let this attempt decide whether to close the circuit
+            state.opened_at = None;
+        }
+        Ok(None)
+    }
+
+    fn after_attempt(&self, _attempt: u32, outcome: &Outcome<'_, E>) -> Option<Verdict> {
+        let mut state = self.state.lock().expect("circuit breaker lock poisoned");
+        if let Outcome::Succeeded = outcome {
+            *state = CircuitState::default();
+            return None;
+        }
+
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= self.threshold {
+            state.opened_at = Some(Instant::now());
+            return Some(Verdict::Fail);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use anyhow::Result;
+
+    use super::*;
+
+    /// Retries immediately, so tests don't wait on the real backoff
+    struct RetryNow(u32);
+
+    impl Layer<anyhow::Error> for RetryNow {
+        fn after_attempt(
+            &self,
+            attempt: u32,
+            outcome: &Outcome<'_, anyhow::Error>,
+        ) -> Option<Verdict> {
+            match outcome {
+                Outcome::Succeeded => None,
+                _ if attempt + 1 >= self.0 => Some(Verdict::Exhausted),
+                _ => Some(Verdict::Retry(Duration::ZERO)),
+            }
+        }
+    }
+
+    async fn fail_times(attempts: &AtomicU32, failures: u32) -> Result<u32> {
+        let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+        if attempt < failures {
+            Err(anyhow!("attempt {attempt} failed"))
+        } else {
+            Ok(attempt)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_success() -> Result<()> {
+        let attempts = AtomicU32::new(0);
+        let policy = RequestPolicy::new().layer(RetryNow(3));
+        assert_eq!(policy.run(|| fail_times(&attempts, 2)).await?, 2);
+
+        let attempts = AtomicU32::new(0);
+        let err = policy.run(|| fail_times(&attempts, 5)).await.unwrap_err();
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert!(err.to_string().contains("too many failures"));
+        assert!(err.to_string().contains("Last error: attempt 2 failed"));
+
+        let exhausted = err.downcast_ref::<RetriesExhausted>().unwrap();
+        let history: Vec<_> = exhausted
+            .attempts
+            .iter()
+            .map(|attempt| attempt.error.to_string())
+            .collect();
+        assert_eq!(
+            history,
+            vec!["attempt 0 failed", "attempt 1 failed", "attempt 2 failed"]
+        );
+        assert!(exhausted
+            .attempts
+            .iter()
+            .all(|attempt| attempt.status().is_none()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_non_retryable_error_fails_immediately() {
+        let attempts = AtomicU32::new(0);
+        let policy = RequestPolicy::new()
+            .layer(RetryNow(3))
+            .layer(Retry::new(3, |_: &anyhow::Error| false));
+        let err = policy.run(|| fail_times(&attempts, 5)).await.unwrap_err();
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        assert_eq!(err.to_string(), "attempt 0 failed");
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let attempts = AtomicU32::new(0);
+        let policy = RequestPolicy::new()
+            .layer(RetryNow(2))
+            .layer(Timeout(Duration::from_millis(10)));
+        let err = policy
+            .run(|| async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                sleep(Duration::from_secs(60)).await;
+                Ok::<_, anyhow::Error>(())
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert!(err.to_string().contains("request timed out after 10ms"));
+        let exhausted = err.downcast_ref::<RetriesExhausted>().unwrap();
+        assert!(exhausted
+            .attempts
+            .iter()
+            .all(|attempt| attempt.latency >= Duration::from_millis(10)));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let attempts = AtomicU32::new(0);
+        let policy = RequestPolicy::new()
+            .layer(RetryNow(5))
+            .layer(CircuitBreaker::new(2, Duration::from_secs(60)));
+        let err = policy.run(|| fail_times(&attempts, 5)).await.unwrap_err();
+        assert_eq!(err.to_string(), "attempt 1 failed");
+
+        // The circuit is now open, so later requests aren't sent at all
+        let err = policy.run(|| fail_times(&attempts, 5)).await.unwrap_err();
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert!(err
+            .to_string()
+            .contains("2 consecutive requests have failed"));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() -> Result<()> {
+        let interval = Duration::from_millis(20);
+        let policy = RequestPolicy::<anyhow::Error>::new().layer(RateLimit::new(interval));
+        let start = Instant::now();
+        for _ in 0..3 {
+            policy.run(|| async { Ok(()) }).await?;
+        }
+        // The first request goes out immediately, the others wait their turn
+        assert!(start.elapsed() >= interval * 2);
+        Ok(())
+    }
 }
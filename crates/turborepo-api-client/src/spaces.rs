@@ -0,0 +1,226 @@
+//! Payloads sent to and received from the Spaces runs API
+//! (`/v0/spaces/{space_id}/runs`). These mirror the structures in
+//! `cli/internal/runsummary/spaces.go`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Running,
+    Completed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpaceClientSummary {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+}
+
+/// The body of a run creation (`POST`) or completion (`PATCH`) request.
+///
+/// A run is created with a start time and `RunStatus::Running`, and later
+/// finished with an end time, exit code and `RunStatus::Completed`, so every
+/// field that only applies to one of the two is optional.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpaceRun {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<RunStatus>,
+    /// Always "TURBO" for runs created by turbo
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub ty: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository_path: Option<String>,
+    /// The host the run was executed on, e.g. a CI vendor or "LOCAL"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<SpaceClientSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_branch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_sha: Option<String>,
+    #[serde(rename = "originationUser", skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+/// The run as returned by the server after creation. The id is needed to
+/// post tasks to and finish the run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreateSpaceRunResponse {
+    pub id: String,
+    pub url: String,
+    /// Fields this client doesn't know about
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CacheSource {
+    Local,
+    Remote,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpaceCacheStatus {
+    pub status: CacheStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<CacheSource>,
+    /// Time saved by the cache hit in milliseconds
+    pub time_saved: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpaceTaskSummary {
+    pub key: String,
+    pub name: String,
+    pub workspace: String,
+    pub hash: String,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub cache: SpaceCacheStatus,
+    pub exit_code: i32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependents: Vec<String>,
+    #[serde(rename = "log")]
+    pub logs: String,
+}
+
+#[cfg(test)]
+mod test {
+    use serde::de::DeserializeOwned;
+    use serde_json::json;
+
+    use super::*;
+
+    fn assert_round_trip<T>(value: T, expected: serde_json::Value)
+    where
+        T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug,
+    {
+        assert_eq!(serde_json::to_value(&value).unwrap(), expected);
+        assert_eq!(serde_json::from_value::<T>(expected).unwrap(), value);
+    }
+
+    #[test]
+    fn test_space_run_created() {
+        let run = SpaceRun {
+            start_time: Some(1680000000000),
+            status: Some(RunStatus::Running),
+            ty: Some("TURBO".to_string()),
+            command: Some("turbo run build".to_string()),
+            repository_path: Some("apps/web".to_string()),
+            context: Some("LOCAL".to_string()),
+            client: Some(SpaceClientSummary {
+                id: "turbo".to_string(),
+                name: "Turbo".to_string(),
+                version: "1.9.0".to_string(),
+            }),
+            git_branch: Some("main".to_string()),
+            git_sha: Some("abc123".to_string()),
+            user: Some("turbobot".to_string()),
+            ..Default::default()
+        };
+        assert_round_trip(
+            run,
+            json!({
+                "startTime": 1680000000000i64,
+                "status": "running",
+                "type": "TURBO",
+                "command": "turbo run build",
+                "repositoryPath": "apps/web",
+                "context": "LOCAL",
+                "client": { "id": "turbo", "name": "Turbo", "version": "1.9.0" },
+                "gitBranch": "main",
+                "gitSha": "abc123",
+                "originationUser": "turbobot"
+            }),
+        );
+    }
+
+    #[test]
+    fn test_space_run_completed() {
+        let run = SpaceRun {
+            end_time: Some(1680000001000),
+            status: Some(RunStatus::Completed),
+            exit_code: Some(1),
+            ..Default::default()
+        };
+        assert_round_trip(
+            run,
+            json!({
+                "endTime": 1680000001000i64,
+                "status": "completed",
+                "exitCode": 1
+            }),
+        );
+    }
+
+    #[test]
+    fn test_space_task_summary() {
+        let task = SpaceTaskSummary {
+            key: "web#build".to_string(),
+            name: "build".to_string(),
+            workspace: "web".to_string(),
+            hash: "0123456789abcdef".to_string(),
+            start_time: 1680000000000,
+            end_time: 1680000000500,
+            cache: SpaceCacheStatus {
+                status: CacheStatus::Hit,
+                source: Some(CacheSource::Remote),
+                time_saved: 1200,
+            },
+            exit_code: 0,
+            dependencies: vec!["ui#build".to_string()],
+            dependents: vec![],
+            logs: "cache hit, replaying output".to_string(),
+        };
+        assert_round_trip(
+            task,
+            json!({
+                "key": "web#build",
+                "name": "build",
+                "workspace": "web",
+                "hash": "0123456789abcdef",
+                "startTime": 1680000000000i64,
+                "endTime": 1680000000500i64,
+                "cache": { "status": "HIT", "source": "REMOTE", "timeSaved": 1200 },
+                "exitCode": 0,
+                "dependencies": ["ui#build"],
+                "log": "cache hit, replaying output"
+            }),
+        );
+    }
+
+    #[test]
+    fn test_cache_miss_omits_source() {
+        let status = SpaceCacheStatus {
+            status: CacheStatus::Miss,
+            source: None,
+            time_saved: 0,
+        };
+        assert_round_trip(status, json!({ "status": "MISS", "timeSaved": 0 }));
+    }
+}
@@ -0,0 +1,198 @@
+//! A bare bones HTTP server for tests that need a real socket to talk to.
+//! Other crates can use it through the `test-utils` feature.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// The path including its query string
+    pub path: String,
+    /// Names are lowercased
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// What `TestServer` answers a request with. `content-length` is set from
+/// the body.
+#[derive(Debug, Clone)]
+pub struct TestResponse {
+    status: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    delay: Duration,
+}
+
+impl TestResponse {
+    /// An empty response with `status`, e.g. `"404 Not Found"`
+    pub fn new(status: impl Into<String>) -> Self {
+        Self {
+            status: status.into(),
+            headers: Vec::new(),
+            body: Vec::new(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub fn json(self, body: impl Into<Vec<u8>>) -> Self {
+        self.header("content-type", "application/json").body(body)
+    }
+
+    /// Waits this long before answering, so that requests overlap
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+type Responder = dyn Fn(&RecordedRequest, &str) -> TestResponse + Send + Sync;
+
+/// Answers one request per connection on an unused local port, and records
+/// every request it receives.
+pub struct TestServer {
+    url: String,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    max_concurrent: Arc<AtomicUsize>,
+}
+
+impl TestServer {
+    /// Starts a server that answers each request with what `respond` returns
+    /// for it. `respond` is also given the server's base URL, for responses
+    /// that point back at the server.
+    pub async fn start(
+        respond: impl Fn(&RecordedRequest, &str) -> TestResponse + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("test server can bind a local port");
+        let url = format!(
+            "http://{}",
+            listener.local_addr().expect("test server has an address")
+        );
+        let server = Self {
+            url: url.clone(),
+            requests: Arc::default(),
+            max_concurrent: Arc::default(),
+        };
+
+        let respond: Arc<Responder> = Arc::new(respond);
+        let requests = server.requests.clone();
+        let max_concurrent = server.max_concurrent.clone();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let connection = Connection {
+                    url: url.clone(),
+                    respond: respond.clone(),
+                    requests: requests.clone(),
+                    max_concurrent: max_concurrent.clone(),
+                    in_flight: in_flight.clone(),
+                };
+                tokio::spawn(connection.serve(stream));
+            }
+        });
+
+        server
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Every request received so far, in the order they were received
+    pub fn requests(&self) -> MutexGuard<'_, Vec<RecordedRequest>> {
+        self.requests.lock().unwrap()
+    }
+
+    /// The most requests that were being answered at once
+    pub fn max_concurrent(&self) -> usize {
+        self.max_concurrent.load(Ordering::SeqCst)
+    }
+}
+
+struct Connection {
+    url: String,
+    respond: Arc<Responder>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    max_concurrent: Arc<AtomicUsize>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Connection {
+    async fn serve(self, stream: TcpStream) -> std::io::Result<()> {
+        let mut stream = BufReader::new(stream);
+        let mut request_line = String::new();
+        stream.read_line(&mut request_line).await?;
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts.next().unwrap_or_default().to_string();
+
+        let mut headers = HashMap::new();
+        loop {
+            let mut header = String::new();
+            stream.read_line(&mut header).await?;
+            let Some((name, value)) = header.trim_end().split_once(':') else {
+                break;
+            };
+            headers.insert(name.to_lowercase(), value.trim().to_string());
+        }
+        let content_length = headers
+            .get("content-length")
+            .map_or(0, |length| length.parse().unwrap_or(0));
+        let mut body = vec![0; content_length];
+        stream.read_exact(&mut body).await?;
+
+        let current = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_concurrent.fetch_max(current, Ordering::SeqCst);
+        let request = RecordedRequest {
+            method,
+            path,
+            headers,
+            body,
+        };
+        let response = (self.respond)(&request, &self.url);
+        let is_head = request.method == "HEAD";
+        self.requests.lock().unwrap().push(request);
+        if !response.delay.is_zero() {
+            tokio::time::sleep(response.delay).await;
+        }
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        let mut head = format!("HTTP/1.1 {}\r\n", response.status);
+        for (name, value) in &response.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str(&format!(
+            "content-length: {}\r\nconnection: close\r\n\r\n",
+            response.body.len()
+        ));
+        let stream = stream.get_mut();
+        stream.write_all(head.as_bytes()).await?;
+        if !is_head {
+            stream.write_all(&response.body).await?;
+        }
+        Ok(())
+    }
+}
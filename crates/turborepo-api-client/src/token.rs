@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// What a token was granted access to. A `User` scope covers the whole
+/// account, including every team the user belongs to, while a `Team` scope
+/// only covers that one team. Scope types this client doesn't know about
+/// deserialize as `Unknown`, so they don't fail the whole token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TokenScope {
+    User {
+        #[serde(default)]
+        origin: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Team {
+        team_id: String,
+        #[serde(default)]
+        origin: Option<String>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenMetadata {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub scopes: Vec<TokenScope>,
+    /// Milliseconds since the epoch
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub token: TokenMetadata,
+    /// Fields this client doesn't know about
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A finding from `check_token_scopes`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScopeWarning {
+    /// The token can act on the user's whole account, not just the team
+    AccountWide,
+    /// The token also grants access to teams other than the one in use
+    ExtraTeams(Vec<String>),
+    /// None of the token's scopes cover the team, so artifact requests
+    /// will be rejected
+    MissingTeam(String),
+}
+
+impl std::fmt::Display for ScopeWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScopeWarning::AccountWide => write!(
+                f,
+                "token has access to your entire account. Consider creating a token scoped to the \
+                 team instead"
+            ),
+            ScopeWarning::ExtraTeams(teams) => write!(
+                f,
+                "token also has access to other teams ({}). Consider creating a token scoped to \
+                 the team instead",
+                teams.join(", ")
+            ),
+            ScopeWarning::MissingTeam(team_id) => {
+                write!(f, "token does not have access to team {team_id}")
+            }
+        }
+    }
+}
+
+/// Compares a token's scopes to what reading and writing artifacts for the
+/// team needs, which is a single team scope. Returns every way the token is
+/// broader or narrower than that.
+///
+/// Scopes only carry team ids, so when the team is only known by
+/// `team_slug` (`team_id` isn't a `team_` id) the team scopes can't be told
+/// apart and only an account wide scope is reported.
+pub fn check_token_scopes(
+    scopes: &[TokenScope],
+    team_id: &str,
+    team_slug: Option<&str>,
+) -> Vec<ScopeWarning> {
+    let mut warnings = Vec::new();
+    let mut has_user_scope = false;
+    let mut has_team_scope = false;
+    let mut extra_teams = Vec::new();
+    for scope in scopes {
+        match scope {
+            TokenScope::User { .. } => has_user_scope = true,
+            TokenScope::Team { team_id: id, .. } if id == team_id => has_team_scope = true,
+            TokenScope::Team { team_id: id, .. } => extra_teams.push(id.clone()),
+            TokenScope::Unknown => {}
+        }
+    }
+
+    if has_user_scope {
+        warnings.push(ScopeWarning::AccountWide);
+    }
+    if !team_id.starts_with("team_") {
+        return warnings;
+    }
+    if !extra_teams.is_empty() {
+        warnings.push(ScopeWarning::ExtraTeams(extra_teams));
+    }
+    if !has_user_scope && !has_team_scope {
+        warnings.push(ScopeWarning::MissingTeam(
+            team_slug.unwrap_or(team_id).to_string(),
+        ));
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn team(team_id: &str) -> TokenScope {
+        TokenScope::Team {
+            team_id: team_id.to_string(),
+            origin: None,
+        }
+    }
+
+    #[test]
+    fn test_deserialize_token() {
+        let response: TokenResponse = serde_json::from_value(json!({
+            "token": {
+                "id": "tok_1",
+                "name": "ci",
+                "type": "oauth2-token",
+                "origin": "github",
+                "scopes": [
+                    { "type": "user", "origin": "saml", "createdAt": 1680000000000u64 },
+                    { "type": "team", "teamId": "team_1", "createdAt": 1680000000000u64 },
+                    { "type": "integration", "integrationId": "icfg_1" }
+                ],
+                "expiresAt": null,
+                "activeAt": 1680000000000u64,
+                "createdAt": 1680000000000u64
+            }
+        }))
+        .unwrap();
+        assert_eq!(
+            response.token.scopes,
+            vec![
+                TokenScope::User {
+                    origin: Some("saml".to_string())
+                },
+                team("team_1"),
+                TokenScope::Unknown
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_token_scopes() {
+        assert_eq!(
+            check_token_scopes(&[team("team_1"), TokenScope::Unknown], "team_1", None),
+            vec![]
+        );
+        assert_eq!(
+            check_token_scopes(&[TokenScope::User { origin: None }], "team_1", None),
+            vec![ScopeWarning::AccountWide]
+        );
+        assert_eq!(
+            check_token_scopes(&[team("team_1"), team("team_2")], "team_1", None),
+            vec![ScopeWarning::ExtraTeams(vec!["team_2".to_string()])]
+        );
+        assert_eq!(
+            check_token_scopes(&[team("team_2")], "team_1", Some("one")),
+            vec![
+                ScopeWarning::ExtraTeams(vec!["team_2".to_string()]),
+                ScopeWarning::MissingTeam("one".to_string())
+            ]
+        );
+
+        // A team only known by its slug can't be matched against team scopes
+        assert_eq!(
+            check_token_scopes(&[team("team_2")], "", Some("one")),
+            vec![]
+        );
+        assert_eq!(
+            check_token_scopes(&[TokenScope::User { origin: None }], "", Some("one")),
+            vec![ScopeWarning::AccountWide]
+        );
+    }
+}
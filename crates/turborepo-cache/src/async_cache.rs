@@ -0,0 +1,258 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use bytes::Bytes;
+use thiserror::Error;
+use tokio::{
+    sync::{mpsc, Semaphore},
+    task::{JoinHandle, JoinSet},
+};
+
+use crate::{Cache, CacheError};
+
+#[derive(Debug, Error)]
+pub enum AsyncCacheError {
+    #[error("the upload queue has been shut down")]
+    Closed,
+}
+
+/// An upload that was queued with `AsyncCache::put` and didn't succeed.
+#[derive(Debug)]
+pub struct UploadFailure {
+    pub key: String,
+    pub error: CacheError,
+}
+
+/// Wraps a cache so that `put` returns as soon as the artifact is queued,
+/// and uploads happen in the background on up to `max_workers` tasks at a
+/// time. Fetches go straight to the wrapped cache.
+///
+/// Queued uploads only finish if the cache is drained with `wait` or
+/// `shutdown` before the runtime stops.
+pub struct AsyncCache<C> {
+    cache: Arc<C>,
+    sender: mpsc::Sender<(String, Bytes)>,
+    shutting_down: Arc<AtomicBool>,
+    dispatcher: JoinHandle<Vec<UploadFailure>>,
+}
+
+impl<C: Cache + 'static> AsyncCache<C> {
+    /// Must be called from within a tokio runtime. At most `max_workers`
+    /// uploads run at once, and as many again can be waiting in the queue
+    /// before `put` waits for room.
+    pub fn new(cache: C, max_workers: usize) -> Self {
+        let max_workers = max_workers.max(1);
+        let cache = Arc::new(cache);
+        let (sender, receiver) = mpsc::channel(max_workers);
+        let shutting_down = Arc::new(AtomicBool::new(false));
+        let dispatcher = tokio::spawn(Self::dispatch(
+            cache.clone(),
+            receiver,
+            max_workers,
+            shutting_down.clone(),
+        ));
+
+        Self {
+            cache,
+            sender,
+            shutting_down,
+            dispatcher,
+        }
+    }
+
+    async fn dispatch(
+        cache: Arc<C>,
+        mut receiver: mpsc::Receiver<(String, Bytes)>,
+        max_workers: usize,
+        shutting_down: Arc<AtomicBool>,
+    ) -> Vec<UploadFailure> {
+        let workers = Arc::new(Semaphore::new(max_workers));
+        let mut uploads = JoinSet::new();
+        while let Some((key, body)) = receiver.recv().await {
+            let permit = workers
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("semaphore is never closed");
+            // Checked once a worker is free, since shutdown may have been
+            // requested while waiting for one
+            if shutting_down.load(Ordering::SeqCst) {
+                continue;
+            }
+            let cache = cache.clone();
+            uploads.spawn(async move {
+                let result = cache.put(&key, body).await;
+                drop(permit);
+                result.err().map(|error| UploadFailure { key, error })
+            });
+        }
+
+        let mut failures = Vec::new();
+        while let Some(result) = uploads.join_next().await {
+            if let Some(failure) = result.expect("upload task panicked") {
+                failures.push(failure);
+            }
+        }
+        failures
+    }
+
+    pub async fn fetch(&self, key: &str) -> Result<Option<Bytes>, CacheError> {
+        self.cache.fetch(key).await
+    }
+
+    pub async fn exists(&self, key: &str) -> Result<bool, CacheError> {
+        self.cache.exists(key).await
+    }
+
+    /// Queues `body` to be stored under `key`. Only waits if the queue is
+    /// full.
+    pub async fn put(&self, key: &str, body: impl Into<Bytes>) -> Result<(), AsyncCacheError> {
+        self.sender
+            .send((key.to_string(), body.into()))
+            .await
+            .map_err(|_| AsyncCacheError::Closed)
+    }
+
+    /// Stops accepting uploads and waits for every queued upload to finish.
+    /// Returns the uploads that failed.
+    pub async fn wait(self) -> Vec<UploadFailure> {
+        drop(self.sender);
+        self.dispatcher.await.expect("upload dispatcher panicked")
+    }
+
+    /// Stops accepting uploads and drops any that haven't started yet, then
+    /// waits for the ones in progress. Returns the uploads that failed.
+    ///
+    /// Uploads stop starting as soon as this is called, not when the returned
+    /// future is first polled.
+    pub fn shutdown(self) -> impl Future<Output = Vec<UploadFailure>> {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        self.wait()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use tokio::sync::Notify;
+
+    use super::*;
+    use crate::{
+        generic_http::{GenericHttpCache, GenericHttpError},
+        test_utils::{MemoryCache, ObjectStore, TestResponse, TestServer},
+    };
+
+    /// Holds every upload until `release` is notified, notifying `started`
+    /// as each one begins.
+    #[derive(Clone, Default)]
+    struct GatedCache {
+        cache: Arc<MemoryCache>,
+        started: Arc<Notify>,
+        release: Arc<Notify>,
+    }
+
+    #[async_trait]
+    impl Cache for GatedCache {
+        async fn fetch(&self, hash: &str) -> Result<Option<Bytes>, CacheError> {
+            self.cache.fetch(hash).await
+        }
+
+        async fn put(&self, hash: &str, body: Bytes) -> Result<(), CacheError> {
+            self.started.notify_one();
+            self.release.notified().await;
+            self.cache.put(hash, body).await
+        }
+
+        async fn exists(&self, hash: &str) -> Result<bool, CacheError> {
+            self.cache.exists(hash).await
+        }
+    }
+
+    /// Stores every `PUT` after a short delay, so that uploads overlap.
+    /// Paths under `/fail/` are rejected.
+    async fn start_server() -> (TestServer, ObjectStore) {
+        let store = ObjectStore::default();
+        let server_store = store.clone();
+        let server = TestServer::start(move |request, _| {
+            let response = if request.path.starts_with("/fail/") {
+                TestResponse::new("500 Internal Server Error")
+            } else {
+                server_store.respond(request)
+            };
+            response.delay(Duration::from_millis(20))
+        })
+        .await;
+        (server, store)
+    }
+
+    #[tokio::test]
+    async fn test_uploads_in_background() -> Result<()> {
+        let (server, store) = start_server().await;
+        let cache = AsyncCache::new(
+            GenericHttpCache::new(reqwest::Client::new(), format!("{}/{{key}}", server.url()))?,
+            2,
+        );
+        for i in 0..6 {
+            cache.put(&format!("hash-{i}"), format!("body {i}")).await?;
+        }
+        let failures = cache.wait().await;
+
+        assert!(failures.is_empty(), "{:?}", failures);
+        let stored = store.objects();
+        assert_eq!(stored.len(), 6);
+        assert_eq!(stored["/hash-3"], b"body 3");
+        assert!(server.max_concurrent() <= 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_uploads_are_reported() -> Result<()> {
+        let (server, _) = start_server().await;
+        let url_template = format!("{}/fail/{{key}}", server.url());
+        let cache = AsyncCache::new(
+            GenericHttpCache::new(reqwest::Client::new(), url_template)?,
+            1,
+        );
+        cache.put("hash", "body").await?;
+        let failures = cache.wait().await;
+
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].key, "hash");
+        assert!(matches!(
+            failures[0].error,
+            CacheError::GenericHttp(GenericHttpError::UnexpectedStatus { .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drops_queued_uploads() -> Result<()> {
+        let gated = GatedCache::default();
+        let cache = AsyncCache::new(gated.clone(), 1);
+
+        // The first upload is in progress, the second is waiting for a
+        // worker and the third is in the queue
+        cache.put("hash-0", "body").await?;
+        gated.started.notified().await;
+        cache.put("hash-1", "body").await?;
+        cache.put("hash-2", "body").await?;
+
+        let shutdown = tokio::spawn(cache.shutdown());
+        gated.release.notify_one();
+        let failures = shutdown.await?;
+
+        assert!(failures.is_empty());
+        let stored = gated.cache.objects.lock().unwrap();
+        assert_eq!(stored.keys().collect::<Vec<_>>(), vec!["hash-0"]);
+        assert_eq!(gated.cache.requests(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+use turbopath::{AbsoluteSystemPath, PathError};
+
+// Reading a prefix of each file keeps estimates cheap on large outputs while
+// still seeing a mix of the files in the set
+const SAMPLE_BYTES_PER_FILE: u64 = 1024 * 1024;
+const SAMPLE_BYTES_TOTAL: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum CompressionError {
+    #[error(transparent)]
+    Path(#[from] PathError),
+    #[error("failed to read {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("failed to compress sample at zstd level {level}: {source}")]
+    Compress { level: i32, source: io::Error },
+}
+
+/// The estimated result of compressing a set of files at one zstd level,
+/// extrapolated from a sample of their contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionEstimate {
+    pub level: i32,
+    /// Total size of the files that were considered
+    pub total_bytes: u64,
+    /// How much of `total_bytes` was actually compressed
+    pub sampled_bytes: u64,
+    pub estimated_compressed_bytes: u64,
+    pub estimated_duration: Duration,
+}
+
+impl CompressionEstimate {
+    /// Compressed size as a fraction of the original size
+    pub fn ratio(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 1.0;
+        }
+        self.estimated_compressed_bytes as f64 / self.total_bytes as f64
+    }
+}
+
+/// Estimates how well the regular files among `paths` compress at each of
+/// `levels`. Up to 1MB from the start of each file, and 16MB overall, is
+/// compressed at each level and the results are scaled up to the full size
+/// of the files. Anything that isn't a regular file is skipped.
+pub fn estimate_compression<P: AsRef<AbsoluteSystemPath>>(
+    paths: &[P],
+    levels: &[i32],
+) -> Result<Vec<CompressionEstimate>, CompressionError> {
+    let mut total_bytes = 0;
+    let mut sample = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        let metadata = path.symlink_metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        total_bytes += metadata.len();
+
+        let budget = (SAMPLE_BYTES_TOTAL - sample.len()) as u64;
+        if budget == 0 {
+            continue;
+        }
+        let io_error = |source| CompressionError::Io {
+            path: path.as_path().to_owned(),
+            source,
+        };
+        File::open(path.as_path())
+            .map_err(io_error)?
+            .take(budget.min(SAMPLE_BYTES_PER_FILE))
+            .read_to_end(&mut sample)
+            .map_err(io_error)?;
+    }
+
+    let sampled_bytes = sample.len() as u64;
+    levels
+        .iter()
+        .map(|&level| {
+            let start = Instant::now();
+            let compressed = zstd::bulk::compress(&sample, level)
+                .map_err(|source| CompressionError::Compress { level, source })?;
+            let elapsed = start.elapsed();
+
+            let (estimated_compressed_bytes, estimated_duration) = if sampled_bytes == 0 {
+                (0, Duration::ZERO)
+            } else {
+                let scale = total_bytes as f64 / sampled_bytes as f64;
+                (
+                    (compressed.len() as f64 * scale) as u64,
+                    elapsed.mul_f64(scale),
+                )
+            };
+            Ok(CompressionEstimate {
+                level,
+                total_bytes,
+                sampled_bytes,
+                estimated_compressed_bytes,
+                estimated_duration,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use anyhow::Result;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_estimate_compression() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = AbsoluteSystemPathBuf::new(dir.path())?;
+        let repetitive = root.join_literal("repetitive.txt");
+        fs::write(&repetitive, "turbo ".repeat(100_000))?;
+        let small = root.join_literal("small.txt");
+        fs::write(&small, "hello")?;
+        let subdir = root.join_literal("dist");
+        subdir.create_dir_all()?;
+
+        let estimates = estimate_compression(&[repetitive, small, subdir], &[1, 19])?;
+        assert_eq!(estimates.len(), 2);
+        for (estimate, level) in estimates.iter().zip([1, 19]) {
+            assert_eq!(estimate.level, level);
+            // The directory is skipped, and both files fit in the sample
+            assert_eq!(estimate.total_bytes, 600_005);
+            assert_eq!(estimate.sampled_bytes, 600_005);
+            assert!(estimate.ratio() < 0.01, "{:?}", estimate);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_estimate_compression_empty() -> Result<()> {
+        let paths: &[AbsoluteSystemPathBuf] = &[];
+        let estimates = estimate_compression(paths, &[3])?;
+        assert_eq!(estimates[0].estimated_compressed_bytes, 0);
+        assert_eq!(estimates[0].ratio(), 1.0);
+        Ok(())
+    }
+}
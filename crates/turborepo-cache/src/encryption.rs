@@ -0,0 +1,313 @@
+//! Envelope encryption for artifacts. Each artifact body is encrypted with
+//! its own random data key, and that data key is stored alongside it wrapped
+//! by a team key encryption key (KEK). The KEK's id is recorded in the
+//! metadata, so KEKs can be rotated or revoked by rewrapping or dropping a
+//! few bytes of metadata, without re-encrypting any artifact bodies.
+
+use std::collections::HashMap;
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const KEY_LEN: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("encryption keys must be {KEY_LEN} bytes")]
+    InvalidKeyLength,
+    #[error("no key encryption key with id {0:?}, it may have been revoked")]
+    UnknownKeyId(String),
+    #[error("invalid encryption metadata: {0}")]
+    InvalidMetadata(String),
+    #[error("failed to encrypt artifact")]
+    Encryption,
+    #[error("failed to decrypt artifact, it may have been tampered with")]
+    Decryption,
+}
+
+/// Wraps and unwraps data keys. Implemented by `LocalKek` for keys held in
+/// memory, and meant to be implemented by KMS providers, which never hand
+/// out the key itself.
+pub trait KeyEncryptionKey: Send + Sync {
+    /// Recorded in the metadata of every artifact this key wraps
+    fn id(&self) -> &str;
+    fn wrap(&self, data_key: &[u8]) -> Result<Vec<u8>, EncryptionError>;
+    fn unwrap(&self, wrapped_key: &[u8]) -> Result<Vec<u8>, EncryptionError>;
+}
+
+/// A 256-bit KEK held in memory, wrapping data keys with AES-256-GCM.
+pub struct LocalKek {
+    id: String,
+    key: LessSafeKey,
+}
+
+impl LocalKek {
+    pub fn new(id: impl Into<String>, key: &[u8]) -> Result<Self, EncryptionError> {
+        Ok(Self {
+            id: id.into(),
+            key: aes_key(key)?,
+        })
+    }
+}
+
+impl KeyEncryptionKey for LocalKek {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn wrap(&self, data_key: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        // The id is authenticated, so a wrapped key can't be relabeled as
+        // belonging to another KEK
+        seal(&self.key, self.id.as_bytes(), data_key)
+    }
+
+    fn unwrap(&self, wrapped_key: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        open(&self.key, self.id.as_bytes(), wrapped_key)
+    }
+}
+
+/// What's needed, besides the team's KEKs, to decrypt an artifact. Stored
+/// with the artifact, e.g. next to its signature tag.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvelopeMetadata {
+    pub key_id: String,
+    /// The artifact's data key, wrapped by the KEK, in base64
+    pub wrapped_key: String,
+}
+
+/// A team's KEKs. New artifacts are encrypted under the active key, and any
+/// key still in the keyring can decrypt. Removing a key revokes access to
+/// every artifact wrapped by it that hasn't been rewrapped.
+pub struct Keyring {
+    active: String,
+    keys: HashMap<String, Box<dyn KeyEncryptionKey>>,
+}
+
+impl Keyring {
+    pub fn new(active: impl KeyEncryptionKey + 'static) -> Self {
+        let mut keyring = Self {
+            active: active.id().to_string(),
+            keys: HashMap::new(),
+        };
+        keyring.insert(active);
+        keyring
+    }
+
+    /// Adds a key that can decrypt, but isn't used for new artifacts, e.g.
+    /// the previous key during a rotation.
+    pub fn insert(&mut self, key: impl KeyEncryptionKey + 'static) {
+        self.keys.insert(key.id().to_string(), Box::new(key));
+    }
+
+    /// Makes `key` the one new artifacts are encrypted under, keeping the
+    /// previous active key for decryption.
+    pub fn rotate(&mut self, key: impl KeyEncryptionKey + 'static) {
+        self.active = key.id().to_string();
+        self.insert(key);
+    }
+
+    /// Removes a key, returning whether it was present. The active key
+    /// can't be revoked, only rotated away from first.
+    pub fn revoke(&mut self, key_id: &str) -> bool {
+        key_id != self.active && self.keys.remove(key_id).is_some()
+    }
+
+    fn key(&self, key_id: &str) -> Result<&dyn KeyEncryptionKey, EncryptionError> {
+        self.keys
+            .get(key_id)
+            .map(|key| key.as_ref())
+            .ok_or_else(|| EncryptionError::UnknownKeyId(key_id.to_string()))
+    }
+
+    fn unwrap_data_key(&self, metadata: &EnvelopeMetadata) -> Result<Vec<u8>, EncryptionError> {
+        let wrapped_key = BASE64_STANDARD
+            .decode(&metadata.wrapped_key)
+            .map_err(|err| EncryptionError::InvalidMetadata(err.to_string()))?;
+        self.key(&metadata.key_id)?.unwrap(&wrapped_key)
+    }
+
+    /// Encrypts `body` under a new data key. The artifact hash is
+    /// authenticated, so the encrypted body only decrypts under that hash.
+    pub fn encrypt(
+        &self,
+        hash: &str,
+        body: &[u8],
+    ) -> Result<(EnvelopeMetadata, Vec<u8>), EncryptionError> {
+        let mut data_key = [0; KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut data_key)
+            .map_err(|_| EncryptionError::Encryption)?;
+        let key = aes_key(&data_key)?;
+        let ciphertext = seal(&key, hash.as_bytes(), body)?;
+
+        let kek = self.key(&self.active)?;
+        let metadata = EnvelopeMetadata {
+            key_id: kek.id().to_string(),
+            wrapped_key: BASE64_STANDARD.encode(kek.wrap(&data_key)?),
+        };
+        Ok((metadata, ciphertext))
+    }
+
+    pub fn decrypt(
+        &self,
+        hash: &str,
+        metadata: &EnvelopeMetadata,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, EncryptionError> {
+        let data_key = self.unwrap_data_key(metadata)?;
+        open(&aes_key(&data_key)?, hash.as_bytes(), ciphertext)
+    }
+
+    /// Rewraps an artifact's data key under the active KEK. Only the
+    /// metadata changes, so this is how artifacts are moved off a key before
+    /// it's revoked.
+    pub fn rewrap(&self, metadata: &EnvelopeMetadata) -> Result<EnvelopeMetadata, EncryptionError> {
+        let data_key = self.unwrap_data_key(metadata)?;
+        let kek = self.key(&self.active)?;
+        Ok(EnvelopeMetadata {
+            key_id: kek.id().to_string(),
+            wrapped_key: BASE64_STANDARD.encode(kek.wrap(&data_key)?),
+        })
+    }
+}
+
+fn aes_key(key: &[u8]) -> Result<LessSafeKey, EncryptionError> {
+    let key = UnboundKey::new(&AES_256_GCM, key).map_err(|_| EncryptionError::InvalidKeyLength)?;
+    Ok(LessSafeKey::new(key))
+}
+
+// Returns the nonce followed by the ciphertext and tag
+fn seal(key: &LessSafeKey, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+    let mut nonce = [0; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| EncryptionError::Encryption)?;
+
+    let mut sealed = Vec::with_capacity(NONCE_LEN + plaintext.len() + AES_256_GCM.tag_len());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(plaintext);
+    let tag = key
+        .seal_in_place_separate_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(aad),
+            &mut sealed[NONCE_LEN..],
+        )
+        .map_err(|_| EncryptionError::Encryption)?;
+    sealed.extend_from_slice(tag.as_ref());
+    Ok(sealed)
+}
+
+fn open(key: &LessSafeKey, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+    if sealed.len() < NONCE_LEN {
+        return Err(EncryptionError::Decryption);
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| EncryptionError::Decryption)?;
+    let mut plaintext = ciphertext.to_vec();
+    let len = key
+        .open_in_place(nonce, Aad::from(aad), &mut plaintext)
+        .map_err(|_| EncryptionError::Decryption)?
+        .len();
+    plaintext.truncate(len);
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kek(id: &str, byte: u8) -> LocalKek {
+        LocalKek::new(id, &[byte; KEY_LEN]).unwrap()
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() -> Result<(), EncryptionError> {
+        let keyring = Keyring::new(kek("team-key-1", 1));
+        let (metadata, ciphertext) = keyring.encrypt("abc123", b"artifact body")?;
+
+        assert_eq!(metadata.key_id, "team-key-1");
+        assert!(!ciphertext
+            .windows(b"artifact body".len())
+            .any(|window| window == b"artifact body"));
+        assert_eq!(
+            keyring.decrypt("abc123", &metadata, &ciphertext)?,
+            b"artifact body"
+        );
+
+        // The body is bound to its hash and can't be modified
+        assert!(matches!(
+            keyring.decrypt("def456", &metadata, &ciphertext),
+            Err(EncryptionError::Decryption)
+        ));
+        let mut tampered = ciphertext.clone();
+        tampered[NONCE_LEN] ^= 1;
+        assert!(matches!(
+            keyring.decrypt("abc123", &metadata, &tampered),
+            Err(EncryptionError::Decryption)
+        ));
+
+        // Nor can the wrapped key be claimed by another KEK
+        let mut other = Keyring::new(kek("team-key-1", 2));
+        other.insert(kek("team-key-2", 1));
+        let relabeled = EnvelopeMetadata {
+            key_id: "team-key-2".to_string(),
+            ..metadata
+        };
+        assert!(matches!(
+            other.decrypt("abc123", &relabeled, &ciphertext),
+            Err(EncryptionError::Decryption)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotate_and_revoke() -> Result<(), EncryptionError> {
+        let mut keyring = Keyring::new(kek("old", 1));
+        let (old_metadata, ciphertext) = keyring.encrypt("abc123", b"artifact body")?;
+        let (unmigrated, other_ciphertext) = keyring.encrypt("def456", b"other body")?;
+
+        keyring.rotate(kek("new", 2));
+        assert!(!keyring.revoke("new"));
+        assert_eq!(
+            keyring.decrypt("abc123", &old_metadata, &ciphertext)?,
+            b"artifact body"
+        );
+
+        // Rewrapping moves an artifact to the new key without touching its
+        // body
+        let new_metadata = keyring.rewrap(&old_metadata)?;
+        assert_eq!(new_metadata.key_id, "new");
+        assert!(keyring.revoke("old"));
+        assert_eq!(
+            keyring.decrypt("abc123", &new_metadata, &ciphertext)?,
+            b"artifact body"
+        );
+        assert!(matches!(
+            keyring.decrypt("def456", &unmigrated, &other_ciphertext),
+            Err(EncryptionError::UnknownKeyId(id)) if id == "old"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata_format() {
+        let metadata = EnvelopeMetadata {
+            key_id: "team-key-1".to_string(),
+            wrapped_key: "AAAA".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&metadata).unwrap(),
+            r#"{"keyId":"team-key-1","wrappedKey":"AAAA"}"#
+        );
+        assert!(matches!(
+            LocalKek::new("short", &[0; 16]),
+            Err(EncryptionError::InvalidKeyLength)
+        ));
+    }
+}
@@ -0,0 +1,282 @@
+//! A `Cache` wrapper that injects failures, for testing how callers cope
+//! with a slow or misbehaving backend. Which operations fail is decided by a
+//! seeded random number generator, so a failing test can be replayed by
+//! running it again with the same seed.
+
+use std::{sync::Mutex, time::Duration};
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use thiserror::Error;
+
+use crate::{Cache, CacheError};
+
+const SERVER_ERROR_STATUSES: [u16; 4] = [500, 502, 503, 504];
+
+#[derive(Debug, Error)]
+pub enum FaultError {
+    #[error("injected server error: {0}")]
+    ServerError(u16),
+    #[error("injected partial write: stored {written} of {len} bytes")]
+    PartialWrite { written: usize, len: usize },
+}
+
+/// How often each fault is injected. Rates are probabilities between 0 and
+/// 1, checked per operation, and the default injects nothing.
+#[derive(Debug, Clone, Default)]
+pub struct FaultConfig {
+    /// Seeds the schedule. The same seed and sequence of operations always
+    /// give the same faults.
+    pub seed: u64,
+    /// Every operation is delayed by up to this long
+    pub max_latency: Duration,
+    /// Any operation fails with a 5xx status
+    pub server_error_rate: f64,
+    /// A fetched artifact is cut short, as if the download was interrupted
+    pub truncate_rate: f64,
+    /// A byte of a fetched artifact is flipped, which should fail its
+    /// signature or decompression
+    pub corrupt_rate: f64,
+    /// Only part of an artifact is stored before the put fails, so a later
+    /// fetch restores a partial artifact
+    pub partial_write_rate: f64,
+}
+
+/// Wraps a cache and injects the faults configured in `FaultConfig` into
+/// its operations.
+///
+/// Operations draw from the schedule in the order they start, so concurrent
+/// callers only see a reproducible schedule if they start operations in the
+/// same order.
+pub struct FaultyCache<C> {
+    cache: C,
+    config: FaultConfig,
+    rng: Mutex<StdRng>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fault {
+    ServerError(u16),
+    Truncate,
+    Corrupt,
+    PartialWrite,
+}
+
+impl<C: Cache> FaultyCache<C> {
+    pub fn new(cache: C, config: FaultConfig) -> Self {
+        Self {
+            rng: Mutex::new(StdRng::seed_from_u64(config.seed)),
+            cache,
+            config,
+        }
+    }
+
+    // Draws the latency and at most one fault for an operation. Every
+    // candidate is rolled, even after one hits, so the number of draws per
+    // operation doesn't depend on the outcome.
+    fn schedule(&self, candidates: &[(Fault, f64)]) -> (Duration, Option<Fault>) {
+        let mut rng = self.rng.lock().expect("fault schedule lock poisoned");
+        let latency = self.config.max_latency.mul_f64(rng.gen::<f64>());
+        let mut fault = None;
+        for &(candidate, rate) in candidates {
+            let hit = rng.gen_bool(rate.clamp(0.0, 1.0));
+            let status = SERVER_ERROR_STATUSES[rng.gen_range(0..SERVER_ERROR_STATUSES.len())];
+            if hit && fault.is_none() {
+                fault = Some(match candidate {
+                    Fault::ServerError(_) => Fault::ServerError(status),
+                    candidate => candidate,
+                });
+            }
+        }
+        (latency, fault)
+    }
+
+    // Flips the middle byte. Empty artifacts are returned unchanged.
+    fn corrupt(body: Bytes) -> Bytes {
+        let mut body = BytesMut::from(&body[..]);
+        let middle = body.len() / 2;
+        if let Some(byte) = body.get_mut(middle) {
+            *byte ^= 0xff;
+        }
+        body.freeze()
+    }
+}
+
+async fn delay(latency: Duration) {
+    if !latency.is_zero() {
+        tokio::time::sleep(latency).await;
+    }
+}
+
+#[async_trait]
+impl<C: Cache> Cache for FaultyCache<C> {
+    async fn fetch(&self, hash: &str) -> Result<Option<Bytes>, CacheError> {
+        let (latency, fault) = self.schedule(&[
+            (Fault::ServerError(0), self.config.server_error_rate),
+            (Fault::Truncate, self.config.truncate_rate),
+            (Fault::Corrupt, self.config.corrupt_rate),
+        ]);
+        delay(latency).await;
+        if let Some(Fault::ServerError(status)) = fault {
+            return Err(FaultError::ServerError(status).into());
+        }
+
+        let body = self.cache.fetch(hash).await?;
+        Ok(body.map(|body| match fault {
+            Some(Fault::Truncate) => body.slice(..body.len() / 2),
+            Some(Fault::Corrupt) => Self::corrupt(body),
+            _ => body,
+        }))
+    }
+
+    async fn put(&self, hash: &str, body: Bytes) -> Result<(), CacheError> {
+        let (latency, fault) = self.schedule(&[
+            (Fault::ServerError(0), self.config.server_error_rate),
+            (Fault::PartialWrite, self.config.partial_write_rate),
+        ]);
+        delay(latency).await;
+        match fault {
+            Some(Fault::ServerError(status)) => Err(FaultError::ServerError(status).into()),
+            Some(Fault::PartialWrite) => {
+                let len = body.len();
+                let written = len / 2;
+                self.cache.put(hash, body.slice(..written)).await?;
+                Err(FaultError::PartialWrite { written, len }.into())
+            }
+            _ => self.cache.put(hash, body).await,
+        }
+    }
+
+    async fn exists(&self, hash: &str) -> Result<bool, CacheError> {
+        let (latency, fault) =
+            self.schedule(&[(Fault::ServerError(0), self.config.server_error_rate)]);
+        delay(latency).await;
+        if let Some(Fault::ServerError(status)) = fault {
+            return Err(FaultError::ServerError(status).into());
+        }
+        self.cache.exists(hash).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use anyhow::Result;
+
+    use super::*;
+    use crate::test_utils::MemoryCache;
+
+    const BODY: &[u8] = b"artifact body";
+
+    fn faulty(config: FaultConfig) -> FaultyCache<MemoryCache> {
+        let cache = MemoryCache::default();
+        cache
+            .objects
+            .lock()
+            .unwrap()
+            .insert("abc123".to_string(), Bytes::from_static(BODY));
+        FaultyCache::new(cache, config)
+    }
+
+    // The outcome of a fixed sequence of operations, as strings so that
+    // errors can be compared
+    async fn run(cache: &FaultyCache<MemoryCache>) -> Vec<String> {
+        let mut outcomes = Vec::new();
+        for _ in 0..20 {
+            outcomes.push(format!("{:?}", cache.fetch("abc123").await));
+            outcomes.push(format!("{:?}", cache.exists("abc123").await));
+        }
+        outcomes
+    }
+
+    #[tokio::test]
+    async fn test_schedule_is_deterministic() {
+        let config = |seed| FaultConfig {
+            seed,
+            server_error_rate: 0.3,
+            truncate_rate: 0.3,
+            corrupt_rate: 0.3,
+            ..FaultConfig::default()
+        };
+
+        let outcomes = run(&faulty(config(1))).await;
+        assert_eq!(outcomes, run(&faulty(config(1))).await);
+        assert_ne!(outcomes, run(&faulty(config(2))).await);
+        assert!(outcomes.iter().any(|outcome| outcome.starts_with("Err")));
+        assert!(outcomes.iter().any(|outcome| outcome.starts_with("Ok")));
+
+        // Without any rates set, nothing is injected
+        let cache = faulty(FaultConfig::default());
+        assert!(run(&cache)
+            .await
+            .iter()
+            .all(|outcome| outcome.starts_with("Ok")));
+    }
+
+    #[tokio::test]
+    async fn test_faults() -> Result<()> {
+        let cache = faulty(FaultConfig {
+            server_error_rate: 1.0,
+            ..FaultConfig::default()
+        });
+        assert!(matches!(
+            cache.exists("abc123").await,
+            Err(CacheError::Fault(FaultError::ServerError(500..=599)))
+        ));
+        assert!(cache.put("def456", Bytes::from_static(BODY)).await.is_err());
+        assert_eq!(cache.cache.requests(), 0);
+
+        let cache = faulty(FaultConfig {
+            truncate_rate: 1.0,
+            ..FaultConfig::default()
+        });
+        assert_eq!(
+            cache.fetch("abc123").await?,
+            Some(Bytes::from_static(&BODY[..BODY.len() / 2]))
+        );
+        assert_eq!(cache.fetch("missing").await?, None);
+
+        let cache = faulty(FaultConfig {
+            corrupt_rate: 1.0,
+            ..FaultConfig::default()
+        });
+        let body = cache.fetch("abc123").await?.unwrap();
+        assert_eq!(body.len(), BODY.len());
+        assert_ne!(body, BODY);
+
+        let cache = faulty(FaultConfig {
+            partial_write_rate: 1.0,
+            ..FaultConfig::default()
+        });
+        assert!(matches!(
+            cache.put("def456", Bytes::from_static(BODY)).await,
+            Err(CacheError::Fault(FaultError::PartialWrite {
+                written: 6,
+                len: 13
+            }))
+        ));
+        assert_eq!(
+            cache.cache.fetch("def456").await?,
+            Some(Bytes::from_static(&BODY[..6]))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_latency() -> Result<()> {
+        let cache = faulty(FaultConfig {
+            seed: 7,
+            max_latency: Duration::from_millis(50),
+            ..FaultConfig::default()
+        });
+        let (expected, _) = faulty(cache.config.clone()).schedule(&[]);
+
+        let start = Instant::now();
+        assert!(cache.exists("abc123").await?);
+        assert!(start.elapsed() >= expected);
+        assert!(expected <= Duration::from_millis(50));
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+//! A cache backend that keeps artifacts on the local disk, so local-only
+//! workflows don't need a remote cache at all. Each artifact is a
+//! zstd-compressed tar stored as `{hash}.tar.zst` under the cache root.
+//! `put` reads the archive through before storing it, so only well-formed
+//! archives whose entries stay inside the directory they're restored into
+//! are ever stored.
+
+use std::{
+    io,
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use thiserror::Error;
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredUnixPathBuf};
+
+use crate::{is_valid_key, Cache, CacheError};
+
+/// Where the cache lives by default, relative to the repository root
+pub const DEFAULT_CACHE_DIR: &str = "node_modules/.cache/turbo";
+
+const ARTIFACT_EXTENSION: &str = "tar.zst";
+
+// Distinguishes temporary files written concurrently by this process
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Error)]
+pub enum FSCacheError {
+    #[error("cache key must be non-empty and path safe: {0:?}")]
+    InvalidKey(String),
+    #[error("artifact {hash} is not a valid .tar.zst archive: {reason}")]
+    InvalidArchive { hash: String, reason: String },
+    #[error("failed to {operation} {}: {source}", path.display())]
+    Io {
+        operation: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl FSCacheError {
+    fn io(operation: &'static str, path: &AbsoluteSystemPath, source: io::Error) -> Self {
+        FSCacheError::Io {
+            operation,
+            path: path.as_path().to_owned(),
+            source,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FSCache {
+    root: AbsoluteSystemPathBuf,
+}
+
+// Reads every entry header of a zstd-compressed tar, checking that each
+// entry's path is relative and doesn't leave the restore directory.
+fn validate_archive(body: &[u8]) -> Result<(), String> {
+    let decoder = zstd::stream::read::Decoder::new(body).map_err(|err| err.to_string())?;
+    let mut archive = tar::Archive::new(decoder);
+    for entry in archive.entries().map_err(|err| err.to_string())? {
+        let entry = entry.map_err(|err| err.to_string())?;
+        AnchoredUnixPathBuf::new(entry.path_bytes().into_owned()).map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+impl FSCache {
+    /// Creates a cache in `DEFAULT_CACHE_DIR` under `repo_root`.
+    pub fn new(repo_root: &AbsoluteSystemPath) -> Self {
+        let root = DEFAULT_CACHE_DIR
+            .split('/')
+            .fold(repo_root.to_owned(), |path, segment| {
+                path.join_literal(segment)
+            });
+        Self::with_root(root)
+    }
+
+    /// Creates a cache that stores artifacts directly in `root`. The
+    /// directory is created on the first `put`.
+    pub fn with_root(root: AbsoluteSystemPathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn root(&self) -> &AbsoluteSystemPath {
+        self.root.as_absolute_path()
+    }
+
+    fn artifact_path(&self, hash: &str) -> Result<AbsoluteSystemPathBuf, FSCacheError> {
+        if !is_valid_key(hash) {
+            return Err(FSCacheError::InvalidKey(hash.to_string()));
+        }
+        Ok(self
+            .root
+            .join_literal(&format!("{hash}.{ARTIFACT_EXTENSION}")))
+    }
+}
+
+#[async_trait]
+impl Cache for FSCache {
+    async fn fetch(&self, hash: &str) -> Result<Option<Bytes>, CacheError> {
+        let path = self.artifact_path(hash)?;
+        match tokio::fs::read(path.as_path()).await {
+            Ok(body) => Ok(Some(Bytes::from(body))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(FSCacheError::io("read", path.as_absolute_path(), err).into()),
+        }
+    }
+
+    async fn put(&self, hash: &str, body: Bytes) -> Result<(), CacheError> {
+        let path = self.artifact_path(hash)?;
+        // Decompressing is CPU bound, so it's kept off the async workers
+        let archive = body.clone();
+        tokio::task::spawn_blocking(move || validate_archive(&archive))
+            .await
+            .map_err(|err| err.to_string())
+            .and_then(|result| result)
+            .map_err(|reason| FSCacheError::InvalidArchive {
+                hash: hash.to_string(),
+                reason,
+            })?;
+
+        tokio::fs::create_dir_all(self.root.as_path())
+            .await
+            .map_err(|err| FSCacheError::io("create", self.root(), err))?;
+
+        // Written next to the artifact and renamed over it, so a concurrent
+        // fetch sees either the old artifact or the whole new one, never a
+        // partial write. The leading dot keeps it from being a valid key.
+        let temp_path = self.root.join_literal(&format!(
+            ".{hash}.{}.{}.tmp",
+            process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        if let Err(err) = tokio::fs::write(temp_path.as_path(), &body).await {
+            tokio::fs::remove_file(temp_path.as_path()).await.ok();
+            return Err(FSCacheError::io("write", temp_path.as_absolute_path(), err).into());
+        }
+        if let Err(err) = tokio::fs::rename(temp_path.as_path(), path.as_path()).await {
+            tokio::fs::remove_file(temp_path.as_path()).await.ok();
+            return Err(FSCacheError::io("rename", path.as_absolute_path(), err).into());
+        }
+        Ok(())
+    }
+
+    async fn exists(&self, hash: &str) -> Result<bool, CacheError> {
+        let path = self.artifact_path(hash)?;
+        match tokio::fs::metadata(path.as_path()).await {
+            Ok(metadata) => Ok(metadata.is_file()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(FSCacheError::io("stat", path.as_absolute_path(), err).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn cache(dir: &TempDir) -> Result<FSCache> {
+        let repo_root = AbsoluteSystemPathBuf::new(dir.path())?;
+        Ok(FSCache::new(repo_root.as_absolute_path()))
+    }
+
+    // A .tar.zst with one file per `(path, contents)`. Paths are written
+    // into the header directly, so unsafe ones can be built too.
+    fn archive(files: &[(&str, &str)]) -> Result<Bytes> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, contents.as_bytes())?;
+        }
+        let tar = builder.into_inner()?;
+        Ok(Bytes::from(zstd::encode_all(tar.as_slice(), 0)?))
+    }
+
+    #[tokio::test]
+    async fn test_put_fetch_exists() -> Result<()> {
+        let dir = TempDir::new()?;
+        let cache = cache(&dir)?;
+        let body = archive(&[("dist/index.js", "console.log(1)")])?;
+
+        assert_eq!(cache.fetch("abc123").await?, None);
+        assert!(!cache.exists("abc123").await?);
+
+        cache.put("abc123", body.clone()).await?;
+        assert!(cache.exists("abc123").await?);
+        assert_eq!(cache.fetch("abc123").await?, Some(body.clone()));
+        assert_eq!(
+            std::fs::read(dir.path().join("node_modules/.cache/turbo/abc123.tar.zst"))?,
+            body
+        );
+
+        // Storing again replaces the artifact and leaves no temporary files
+        let new_body = archive(&[("dist/index.js", "console.log(2)")])?;
+        cache.put("abc123", new_body.clone()).await?;
+        assert_eq!(cache.fetch("abc123").await?, Some(new_body));
+        assert_eq!(std::fs::read_dir(cache.root().as_path())?.count(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_archives() -> Result<()> {
+        let dir = TempDir::new()?;
+        let cache = cache(&dir)?;
+
+        let not_zstd = Bytes::from("artifact body");
+        let not_tar = Bytes::from(zstd::encode_all(&b"artifact body"[..], 0)?);
+        let absolute = archive(&[("/etc/passwd", "root")])?;
+        let escaping = archive(&[("dist/../../index.js", "console.log(1)")])?;
+        for body in [not_zstd, not_tar, absolute, escaping] {
+            assert!(matches!(
+                cache.put("abc123", body).await,
+                Err(CacheError::Fs(FSCacheError::InvalidArchive { .. }))
+            ));
+        }
+        assert!(!cache.exists("abc123").await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_keys() -> Result<()> {
+        let dir = TempDir::new()?;
+        let cache = cache(&dir)?;
+        let body = archive(&[("dist/index.js", "console.log(1)")])?;
+
+        for key in ["", "../abc123", ".abc123", "abc/123"] {
+            assert!(matches!(
+                cache.put(key, body.clone()).await,
+                Err(CacheError::Fs(FSCacheError::InvalidKey(_)))
+            ));
+            assert!(cache.fetch(key).await.is_err());
+        }
+        assert!(!dir.path().join("node_modules").exists());
+        Ok(())
+    }
+}
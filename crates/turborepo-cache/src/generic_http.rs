@@ -0,0 +1,257 @@
+//! A cache backend for generic key/value HTTP servers, such as nginx with
+//! WebDAV or sccache compatible endpoints. Artifacts are fetched with `GET`,
+//! checked for with `HEAD` and stored with `PUT` at a URL built from a
+//! template, with no other protocol on top. Servers that need credentials
+//! get them through a single configurable header.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    RequestBuilder, StatusCode,
+};
+use thiserror::Error;
+
+use crate::{is_valid_key, Cache, CacheError};
+
+const KEY_PLACEHOLDER: &str = "{key}";
+
+#[derive(Debug, Error)]
+pub enum GenericHttpError {
+    #[error("cache URL template must contain {KEY_PLACEHOLDER}: {0}")]
+    InvalidTemplate(String),
+    #[error("cache key must be non-empty and URL safe: {0:?}")]
+    InvalidKey(String),
+    #[error("invalid cache auth header {0:?}")]
+    InvalidAuthHeader(String),
+    #[error("cache request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("unexpected status {status} from {url}")]
+    UnexpectedStatus { status: StatusCode, url: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct GenericHttpCache {
+    client: reqwest::Client,
+    url_template: String,
+    auth_header: Option<(HeaderName, HeaderValue)>,
+}
+
+impl GenericHttpCache {
+    /// Creates a backend that stores each artifact at `url_template` with
+    /// `{key}` replaced by the artifact hash, e.g.
+    /// `https://cache.example.com/turbo/{key}.tar.zst`.
+    pub fn new(
+        client: reqwest::Client,
+        url_template: impl Into<String>,
+    ) -> Result<Self, GenericHttpError> {
+        let url_template = url_template.into();
+        if !url_template.contains(KEY_PLACEHOLDER) {
+            return Err(GenericHttpError::InvalidTemplate(url_template));
+        }
+        Ok(Self {
+            client,
+            url_template,
+            auth_header: None,
+        })
+    }
+
+    /// Creates a backend that stores each artifact at `{base_url}/{hash}`.
+    pub fn from_base_url(
+        client: reqwest::Client,
+        base_url: &str,
+    ) -> Result<Self, GenericHttpError> {
+        let base_url = base_url.trim_end_matches('/');
+        Self::new(client, format!("{base_url}/{KEY_PLACEHOLDER}"))
+    }
+
+    /// Sends `name: value` with every request, e.g. `Authorization: Bearer
+    /// ...` or whatever API key header the server expects. The value is
+    /// marked sensitive so it's kept out of debug output.
+    pub fn with_auth_header(mut self, name: &str, value: &str) -> Result<Self, GenericHttpError> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| GenericHttpError::InvalidAuthHeader(name.to_string()))?;
+        let mut value = HeaderValue::from_str(value)
+            .map_err(|_| GenericHttpError::InvalidAuthHeader(name.to_string()))?;
+        value.set_sensitive(true);
+        self.auth_header = Some((name, value));
+        Ok(self)
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.auth_header {
+            Some((name, value)) => request.header(name.clone(), value.clone()),
+            None => request,
+        }
+    }
+
+    fn url_for(&self, key: &str) -> Result<String, GenericHttpError> {
+        if !is_valid_key(key) {
+            return Err(GenericHttpError::InvalidKey(key.to_string()));
+        }
+        Ok(self.url_template.replace(KEY_PLACEHOLDER, key))
+    }
+
+    /// Fetches the artifact stored under `key`, or `None` if the server
+    /// doesn't have it.
+    pub async fn get(&self, key: &str) -> Result<Option<Bytes>, GenericHttpError> {
+        let url = self.url_for(key)?;
+        let response = self.authorize(self.client.get(&url)).send().await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(Some(response.bytes().await?)),
+            status => Err(GenericHttpError::UnexpectedStatus { status, url }),
+        }
+    }
+
+    /// Checks whether the server has something stored under `key`.
+    pub async fn exists(&self, key: &str) -> Result<bool, GenericHttpError> {
+        let url = self.url_for(key)?;
+        let response = self.authorize(self.client.head(&url)).send().await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(GenericHttpError::UnexpectedStatus { status, url }),
+        }
+    }
+
+    /// Stores `body` under `key`, replacing anything already there.
+    pub async fn put(&self, key: &str, body: impl Into<Bytes>) -> Result<(), GenericHttpError> {
+        let url = self.url_for(key)?;
+        let response = self
+            .authorize(self.client.put(&url))
+            .header("Content-Type", "application/octet-stream")
+            .body(body.into())
+            .send()
+            .await?;
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            status => Err(GenericHttpError::UnexpectedStatus { status, url }),
+        }
+    }
+}
+
+#[async_trait]
+impl Cache for GenericHttpCache {
+    async fn fetch(&self, hash: &str) -> Result<Option<Bytes>, CacheError> {
+        Ok(self.get(hash).await?)
+    }
+
+    async fn put(&self, hash: &str, body: Bytes) -> Result<(), CacheError> {
+        Ok(GenericHttpCache::put(self, hash, body).await?)
+    }
+
+    async fn exists(&self, hash: &str) -> Result<bool, CacheError> {
+        Ok(GenericHttpCache::exists(self, hash).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::test_utils::{ObjectStore, TestResponse, TestServer};
+
+    /// An object store that rejects requests without `auth_header`, if one
+    /// is given.
+    async fn start_server(auth_header: Option<(&'static str, &'static str)>) -> TestServer {
+        let store = ObjectStore::default();
+        TestServer::start(move |request, _| match auth_header {
+            Some((name, value))
+                if request
+                    .headers
+                    .get(&name.to_lowercase())
+                    .map(String::as_str)
+                    != Some(value) =>
+            {
+                TestResponse::new("401 Unauthorized")
+            }
+            _ => store.respond(request),
+        })
+        .await
+    }
+
+    #[test]
+    fn test_url_template() {
+        let client = reqwest::Client::new();
+        assert!(matches!(
+            GenericHttpCache::new(client.clone(), "https://cache.example.com/"),
+            Err(GenericHttpError::InvalidTemplate(_))
+        ));
+
+        let cache =
+            GenericHttpCache::new(client, "https://cache.example.com/turbo/{key}.tar.zst").unwrap();
+        assert_eq!(
+            cache.url_for("abc123").unwrap(),
+            "https://cache.example.com/turbo/abc123.tar.zst"
+        );
+        for bad_key in ["", "../etc", "a/b", "a?b", ".hidden"] {
+            assert!(
+                matches!(cache.url_for(bad_key), Err(GenericHttpError::InvalidKey(_))),
+                "{bad_key:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_put() -> Result<()> {
+        let server = start_server(None).await;
+        let cache =
+            GenericHttpCache::new(reqwest::Client::new(), format!("{}/{{key}}", server.url()))?;
+
+        assert_eq!(cache.get("abc123").await?, None);
+        cache.put("abc123", b"artifact body".to_vec()).await?;
+        assert_eq!(
+            cache.get("abc123").await?.as_deref(),
+            Some(&b"artifact body"[..])
+        );
+        assert_eq!(cache.get("def456").await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_auth_header() -> Result<()> {
+        let server = start_server(Some(("x-api-key", "secret"))).await;
+        let base_url = server.url();
+        let unauthorized = GenericHttpCache::from_base_url(reqwest::Client::new(), base_url)?;
+        assert!(matches!(
+            unauthorized.get("abc123").await,
+            Err(GenericHttpError::UnexpectedStatus {
+                status: StatusCode::UNAUTHORIZED,
+                ..
+            })
+        ));
+
+        let cache =
+            GenericHttpCache::from_base_url(reqwest::Client::new(), &format!("{base_url}/"))?
+                .with_auth_header("X-Api-Key", "secret")?;
+        assert_eq!(cache.url_for("abc123")?, format!("{base_url}/abc123"));
+        cache.put("abc123", b"artifact body".to_vec()).await?;
+        assert!(cache.exists("abc123").await?);
+
+        assert!(matches!(
+            cache.with_auth_header("bad header", "secret"),
+            Err(GenericHttpError::InvalidAuthHeader(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cache_trait() -> Result<()> {
+        let server = start_server(None).await;
+        let cache: Box<dyn Cache> = Box::new(GenericHttpCache::new(
+            reqwest::Client::new(),
+            format!("{}/{{key}}", server.url()),
+        )?);
+
+        assert!(!cache.exists("abc123").await?);
+        cache.put("abc123", Bytes::from("artifact body")).await?;
+        assert!(cache.exists("abc123").await?);
+        assert_eq!(
+            cache.fetch("abc123").await?,
+            Some(Bytes::from("artifact body"))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use std::{hash::Hasher as _, str::FromStr};
+
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use twox_hash::XxHash64;
+
+/// The function used to hash file contents. Hashes are only comparable when
+/// they were computed with the same algorithm, so it's recorded alongside
+/// them, including in an artifact's metadata.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    /// Fast, but not collision resistant. Fine for detecting changes, not for
+    /// anything an attacker controls.
+    #[default]
+    Xxhash64,
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Xxhash64 => "xxhash64",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    pub fn hasher(&self) -> ContentHasher {
+        let state = match self {
+            HashAlgorithm::Xxhash64 => HasherState::Xxhash64(XxHash64::with_seed(0)),
+            HashAlgorithm::Sha256 => HasherState::Sha256(digest::Context::new(&digest::SHA256)),
+            HashAlgorithm::Blake3 => HasherState::Blake3(Box::default()),
+        };
+        ContentHasher { state }
+    }
+
+    /// Hashes `bytes` in one go, returning the hex encoded hash
+    pub fn hash(&self, bytes: &[u8]) -> String {
+        let mut hasher = self.hasher();
+        hasher.update(bytes);
+        hasher.finish()
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown hash algorithm: {0}")]
+pub struct UnknownHashAlgorithm(String);
+
+/// Parses the names `as_str` returns, e.g. the algorithm recorded in an
+/// artifact's `x-artifact-hash-algorithm` header
+impl FromStr for HashAlgorithm {
+    type Err = UnknownHashAlgorithm;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "xxhash64" => Ok(HashAlgorithm::Xxhash64),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            _ => Err(UnknownHashAlgorithm(name.to_string())),
+        }
+    }
+}
+
+/// An in-progress hash, created with `HashAlgorithm::hasher`.
+pub struct ContentHasher {
+    state: HasherState,
+}
+
+enum HasherState {
+    Xxhash64(XxHash64),
+    Sha256(digest::Context),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl ContentHasher {
+    pub fn update(&mut self, bytes: &[u8]) {
+        match &mut self.state {
+            HasherState::Xxhash64(hasher) => hasher.write(bytes),
+            HasherState::Sha256(context) => context.update(bytes),
+            HasherState::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+        }
+    }
+
+    /// Returns the hex encoded hash
+    pub fn finish(self) -> String {
+        match self.state {
+            HasherState::Xxhash64(hasher) => format!("{:016x}", hasher.finish()),
+            HasherState::Sha256(context) => to_hex(context.finish().as_ref()),
+            HasherState::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_algorithms() {
+        assert_eq!(
+            HashAlgorithm::Sha256.hash(b"a"),
+            "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb"
+        );
+        assert_eq!(HashAlgorithm::Xxhash64.hash(b"a"), "d24ec4f1a98c6e5b");
+        assert_eq!(
+            HashAlgorithm::Blake3.hash(b"a"),
+            "17762fddd969a453925d65717ac3eea21320b66b54342fde15128d6caf21215f"
+        );
+
+        // Streaming in chunks gives the same hash
+        for algorithm in [
+            HashAlgorithm::Xxhash64,
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Blake3,
+        ] {
+            let mut hasher = algorithm.hasher();
+            hasher.update(b"hello ");
+            hasher.update(b"world");
+            assert_eq!(hasher.finish(), algorithm.hash(b"hello world"));
+        }
+    }
+
+    #[test]
+    fn test_serialized_names() {
+        assert_eq!(
+            serde_json::to_string(&HashAlgorithm::Xxhash64).unwrap(),
+            "\"xxhash64\""
+        );
+        let algorithm: HashAlgorithm = serde_json::from_str("\"blake3\"").unwrap();
+        assert_eq!(algorithm.as_str(), "blake3");
+
+        for algorithm in [
+            HashAlgorithm::Xxhash64,
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Blake3,
+        ] {
+            assert_eq!(
+                algorithm.as_str().parse::<HashAlgorithm>().ok(),
+                Some(algorithm)
+            );
+        }
+        assert!("md5".parse::<HashAlgorithm>().is_err());
+        assert_eq!(HashAlgorithm::default(), HashAlgorithm::Xxhash64);
+    }
+}
@@ -1 +1,61 @@
+pub mod async_cache;
+pub mod compression;
+pub mod encryption;
+pub mod fault_injection;
+pub mod fs;
+#[cfg(feature = "gcs")]
+pub mod gcs;
+pub mod generic_http;
+pub mod hash;
+pub mod negative_cache;
 pub mod signature_authentication;
+pub mod snapshot;
+#[cfg(test)]
+mod test_utils;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use thiserror::Error;
+
+use crate::{fault_injection::FaultError, fs::FSCacheError, generic_http::GenericHttpError};
+
+#[derive(Debug, Error)]
+pub enum CacheError {
+    #[error(transparent)]
+    GenericHttp(#[from] GenericHttpError),
+    #[cfg(feature = "gcs")]
+    #[error(transparent)]
+    Gcs(#[from] gcs::GcsError),
+    #[error(transparent)]
+    Fs(#[from] FSCacheError),
+    #[error(transparent)]
+    Fault(#[from] FaultError),
+    /// An error from a `Cache` implemented outside of this crate
+    #[error(transparent)]
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+// Keys are hashes, which backends put into URLs and object names, so
+// anything that would need escaping or could traverse paths is a mistake
+pub(crate) fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with('.')
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || b == b'.')
+}
+
+/// A store of artifacts keyed by their hash. Implemented by each backend, so
+/// callers can swap backends, or use a mock in tests, without changing their
+/// code.
+#[async_trait]
+pub trait Cache: Send + Sync {
+    /// Fetches the artifact stored under `hash`, or `None` if there isn't one.
+    async fn fetch(&self, hash: &str) -> Result<Option<Bytes>, CacheError>;
+
+    /// Stores `body` under `hash`, replacing anything already there.
+    async fn put(&self, hash: &str, body: Bytes) -> Result<(), CacheError>;
+
+    /// Checks whether an artifact is stored under `hash` without fetching it.
+    async fn exists(&self, hash: &str) -> Result<bool, CacheError>;
+}
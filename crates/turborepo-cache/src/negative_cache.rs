@@ -0,0 +1,205 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
+
+use crate::{Cache, CacheError};
+
+/// Wraps a cache, usually a remote one, and remembers which hashes it
+/// didn't have for `ttl`. Repeated lookups of the same missing hash, e.g.
+/// from several tasks that depend on the same output, are answered without
+/// another round trip until then. Storing a hash through the wrapper forgets
+/// its miss.
+///
+/// Misses only live as long as the wrapper, unless it's created with `load`
+/// and written back with `save`.
+pub struct NegativeCache<C> {
+    cache: C,
+    ttl: Duration,
+    // When each miss stops being trusted
+    misses: Mutex<HashMap<String, SystemTime>>,
+    file: Option<AbsoluteSystemPathBuf>,
+}
+
+impl<C: Cache> NegativeCache<C> {
+    pub fn new(cache: C, ttl: Duration) -> Self {
+        Self {
+            cache,
+            ttl,
+            misses: Mutex::default(),
+            file: None,
+        }
+    }
+
+    /// Like `new`, but starts with the unexpired misses saved in `file`, if
+    /// it exists, and saves back to it.
+    pub fn load(cache: C, ttl: Duration, file: &AbsoluteSystemPath) -> Result<Self, io::Error> {
+        let misses = match fs::read(file.as_path()) {
+            Ok(contents) => {
+                let saved: HashMap<String, u64> = serde_json::from_slice(&contents)?;
+                let now = SystemTime::now();
+                saved
+                    .into_iter()
+                    .map(|(hash, expires_ms)| {
+                        (hash, UNIX_EPOCH + Duration::from_millis(expires_ms))
+                    })
+                    .filter(|(_, expires)| *expires > now)
+                    .collect()
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err),
+        };
+
+        Ok(Self {
+            cache,
+            ttl,
+            misses: Mutex::new(misses),
+            file: Some(file.to_owned()),
+        })
+    }
+
+    /// Writes the unexpired misses to the file given to `load`. Does nothing
+    /// for a wrapper created with `new`.
+    pub fn save(&self) -> Result<(), io::Error> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let now = SystemTime::now();
+        let misses = self.misses.lock().expect("miss lock poisoned");
+        let saved: HashMap<&str, u64> = misses
+            .iter()
+            .filter(|(_, expires)| **expires > now)
+            .map(|(hash, expires)| {
+                let expires_ms = expires
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis()
+                    .try_into()
+                    .unwrap_or(u64::MAX);
+                (hash.as_str(), expires_ms)
+            })
+            .collect();
+        fs::write(file.as_path(), serde_json::to_vec(&saved)?)
+    }
+
+    fn is_known_miss(&self, hash: &str) -> bool {
+        let mut misses = self.misses.lock().expect("miss lock poisoned");
+        match misses.get(hash) {
+            Some(expires) if *expires > SystemTime::now() => true,
+            Some(_) => {
+                misses.remove(hash);
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn record_miss(&self, hash: &str) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        self.misses
+            .lock()
+            .expect("miss lock poisoned")
+            .insert(hash.to_string(), SystemTime::now() + self.ttl);
+    }
+}
+
+#[async_trait]
+impl<C: Cache> Cache for NegativeCache<C> {
+    async fn fetch(&self, hash: &str) -> Result<Option<Bytes>, CacheError> {
+        if self.is_known_miss(hash) {
+            return Ok(None);
+        }
+        let body = self.cache.fetch(hash).await?;
+        if body.is_none() {
+            self.record_miss(hash);
+        }
+        Ok(body)
+    }
+
+    async fn put(&self, hash: &str, body: Bytes) -> Result<(), CacheError> {
+        self.cache.put(hash, body).await?;
+        self.misses.lock().expect("miss lock poisoned").remove(hash);
+        Ok(())
+    }
+
+    async fn exists(&self, hash: &str) -> Result<bool, CacheError> {
+        if self.is_known_miss(hash) {
+            return Ok(false);
+        }
+        let exists = self.cache.exists(hash).await?;
+        if !exists {
+            self.record_miss(hash);
+        }
+        Ok(exists)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::test_utils::MemoryCache;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[tokio::test]
+    async fn test_misses_are_remembered() -> Result<()> {
+        let cache = NegativeCache::new(MemoryCache::default(), TTL);
+
+        assert_eq!(cache.fetch("abc123").await?, None);
+        assert!(!cache.exists("abc123").await?);
+        assert_eq!(cache.fetch("abc123").await?, None);
+        assert_eq!(cache.cache.requests(), 1);
+
+        // Hits aren't remembered
+        cache.cache.put("def456", Bytes::from("body")).await?;
+        assert!(cache.exists("def456").await?);
+        assert!(cache.exists("def456").await?);
+        assert_eq!(cache.cache.requests(), 4);
+
+        // Storing a hash makes it visible straight away
+        cache.put("abc123", Bytes::from("body")).await?;
+        assert_eq!(cache.fetch("abc123").await?, Some(Bytes::from("body")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_zero_ttl_disables_caching() -> Result<()> {
+        let cache = NegativeCache::new(MemoryCache::default(), Duration::ZERO);
+        assert_eq!(cache.fetch("abc123").await?, None);
+        assert_eq!(cache.fetch("abc123").await?, None);
+        assert_eq!(cache.cache.requests(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load() -> Result<()> {
+        let dir = TempDir::new()?;
+        let file = AbsoluteSystemPathBuf::new(dir.path().join("misses.json"))?;
+
+        let cache = NegativeCache::load(MemoryCache::default(), TTL, file.as_absolute_path())?;
+        assert!(!cache.exists("abc123").await?);
+        cache.save()?;
+
+        let cache = NegativeCache::load(MemoryCache::default(), TTL, file.as_absolute_path())?;
+        assert!(!cache.exists("abc123").await?);
+        assert_eq!(cache.cache.requests(), 0);
+
+        // Expired misses are dropped on load
+        fs::write(file.as_path(), r#"{"abc123": 1000}"#)?;
+        let cache = NegativeCache::load(MemoryCache::default(), TTL, file.as_absolute_path())?;
+        assert!(!cache.exists("abc123").await?);
+        assert_eq!(cache.cache.requests(), 1);
+        Ok(())
+    }
+}
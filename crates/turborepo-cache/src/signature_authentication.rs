@@ -1,33 +1,154 @@
-use std::env;
+use std::{
+    env,
+    fs::OpenOptions,
+    io::{self, Write},
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+};
 
 use base64::{prelude::BASE64_STANDARD, Engine};
+use once_cell::sync::OnceCell;
 use os_str_bytes::OsStringBytes;
 use ring::{
-    hmac,
+    digest, hmac,
     hmac::{Algorithm, Tag, HMAC_SHA256},
+    pbkdf2,
 };
+use serde::Serialize;
+use serde_json::json;
 use thiserror::Error;
+use tracing::warn;
 
 #[derive(Debug, Error)]
 pub enum SignatureError {
     #[error(
         "signature secret key not found. You must specify a secret key in the \
-         TURBO_REMOTE_CACHE_SIGNATURE_KEY environment variable"
+         TURBO_REMOTE_CACHE_SIGNATURE_KEY environment variable, or a passphrase in the \
+         TURBO_REMOTE_CACHE_SIGNATURE_PASSPHRASE environment variable"
     )]
     NoSignatureSecretKey,
     #[error("serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
+    /// No longer returned: `validate` reports malformed tags as `Ok(false)`.
+    /// Kept so that existing matches on this enum still compile.
+    #[deprecated(note = "malformed tags are reported as a failed validation instead")]
     #[error("base64 encoding error: {0}")]
     Base64EncodingError(#[from] base64::DecodeError),
+    #[error("failed to write signature diagnostics to {path}: {source}")]
+    Diagnostics { path: PathBuf, source: io::Error },
 }
 
 static TURBO_HMAC_ALGORITHM: Algorithm = HMAC_SHA256;
 
+static TURBO_KEY_DERIVATION_ALGORITHM: pbkdf2::Algorithm = pbkdf2::PBKDF2_HMAC_SHA256;
+// Changing this changes every derived key, which invalidates every tag
+// created from a passphrase.
+const TURBO_KEY_DERIVATION_ITERATIONS: u32 = 100_000;
+const TURBO_DERIVED_KEY_LEN: usize = 32;
+
+// Length of an HMAC-SHA256 tag, and of that tag in padded base64
+const TURBO_TAG_LEN: usize = 32;
+const TURBO_ENCODED_TAG_LEN: usize = 44;
+
+// Marks tags whose metadata also covers the body length and compression.
+// Tags without it are legacy tags over just the hash and team id.
+const FRAMED_TAG_PREFIX: &str = "v2:";
+
+// Enough of a tag to tell two apart, without writing out a usable one
+const DIAGNOSTIC_TAG_PREFIX_LEN: usize = 8;
+// Signed with the secret key to identify it. A plain digest of the key would
+// let anyone holding a diagnostics file test guesses of a weak key offline.
+const KEY_ID_DOMAIN: &[u8] = b"turbo-key-id";
+// Bytes of the key id signature that are written out
+const KEY_ID_LEN: usize = 8;
+
+/// The compression an artifact body is declared to use. Framed tags sign
+/// this, so a tag can't be moved onto the same bytes framed differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtifactCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl ArtifactCompression {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArtifactCompression::None => "none",
+            ArtifactCompression::Gzip => "gzip",
+            ArtifactCompression::Zstd => "zstd",
+        }
+    }
+}
+
+/// Derives a secret key from a human-memorable passphrase. The team id is
+/// used as the salt, so every machine in a team derives the same key from
+/// the same passphrase, while different teams using the same passphrase end
+/// up with different keys.
+pub fn derive_key_from_passphrase(passphrase: &[u8], team_id: &[u8]) -> Vec<u8> {
+    let iterations =
+        NonZeroU32::new(TURBO_KEY_DERIVATION_ITERATIONS).expect("iteration count is non-zero");
+    let mut key = vec![0; TURBO_DERIVED_KEY_LEN];
+    pbkdf2::derive(
+        TURBO_KEY_DERIVATION_ALGORITHM,
+        iterations,
+        team_id,
+        passphrase,
+        &mut key,
+    );
+    key
+}
+
+/// What went into checking a tag that didn't validate, for telling a key
+/// mismatch apart from a corrupted body without sharing the key or the
+/// artifact. If `key_id` differs between the uploading and downloading
+/// machines the keys differ; if it matches but `body_sha256` differs from
+/// the uploaded body's, the body was changed in transit or storage.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureDiagnostics {
+    /// Whether the tag was a framed (`v2:`) tag
+    pub framed: bool,
+    /// The start of the tag the artifact came with
+    pub expected_tag_prefix: String,
+    /// The start of the tag computed for the artifact with our key
+    pub computed_tag_prefix: String,
+    /// The signed fields other than the body
+    pub metadata: serde_json::Value,
+    /// The start of an HMAC of a fixed string keyed by the secret key, in hex
+    pub key_id: String,
+    pub body_len: usize,
+    pub body_sha256: String,
+}
+
+impl SignatureDiagnostics {
+    /// Appends the diagnostics to `path` as a line of JSON, so that every
+    /// failure in a run is kept.
+    pub fn write_to(&self, path: &Path) -> Result<(), SignatureError> {
+        let mut line = serde_json::to_vec(self)?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(&line))
+            .map_err(|source| SignatureError::Diagnostics {
+                path: path.to_owned(),
+                source,
+            })
+    }
+}
+
 #[derive(Debug)]
 pub struct ArtifactSignatureAuthenticator {
     team_id: Vec<u8>,
     // An override for testing purposes (to avoid env var race conditions)
     secret_key_override: Option<Vec<u8>>,
+    allow_legacy_tags: bool,
+    diagnostics_file: Option<PathBuf>,
+    // The key derived from TURBO_REMOTE_CACHE_SIGNATURE_PASSPHRASE. Deriving
+    // it is deliberately slow, so it's only done once per authenticator.
+    passphrase_key: OnceCell<Vec<u8>>,
 }
 
 impl ArtifactSignatureAuthenticator {
@@ -35,10 +156,37 @@ impl ArtifactSignatureAuthenticator {
         Self {
             team_id,
             secret_key_override,
+            allow_legacy_tags: true,
+            diagnostics_file: None,
+            passphrase_key: OnceCell::new(),
         }
     }
 
+    /// Writes `SignatureDiagnostics` to `path` whenever a tag fails to
+    /// validate. Off by default.
+    pub fn with_diagnostics_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.diagnostics_file = Some(path.into());
+        self
+    }
+
+    /// Sets whether `validate_framed` accepts legacy tags, which don't sign
+    /// the body length or compression. They are accepted by default so
+    /// artifacts uploaded by older clients keep validating.
+    pub fn with_legacy_tags(mut self, allow_legacy_tags: bool) -> Self {
+        self.allow_legacy_tags = allow_legacy_tags;
+        self
+    }
+
+    /// Creates an authenticator whose secret key is derived from `passphrase`
+    /// rather than read from the environment.
+    pub fn from_passphrase(team_id: Vec<u8>, passphrase: &[u8]) -> Self {
+        let secret_key = derive_key_from_passphrase(passphrase, &team_id);
+        Self::new(team_id, Some(secret_key))
+    }
+
     // Gets secret key from either secret key override or environment variable.
+    // A raw key in TURBO_REMOTE_CACHE_SIGNATURE_KEY takes precedence over a
+    // passphrase in TURBO_REMOTE_CACHE_SIGNATURE_PASSPHRASE.
     // HMAC_SHA256 has no key length limit, although it's generally recommended
     // to keep key length under 64 bytes since anything longer is hashed using
     // SHA-256.
@@ -47,9 +195,17 @@ impl ArtifactSignatureAuthenticator {
             return Ok(secret_key.to_vec());
         }
 
-        Ok(env::var_os("TURBO_REMOTE_CACHE_SIGNATURE_KEY")
-            .ok_or(SignatureError::NoSignatureSecretKey)?
-            .into_raw_vec())
+        if let Some(secret_key) = env::var_os("TURBO_REMOTE_CACHE_SIGNATURE_KEY") {
+            return Ok(secret_key.into_raw_vec());
+        }
+
+        let secret_key = self.passphrase_key.get_or_try_init(|| {
+            let passphrase = env::var_os("TURBO_REMOTE_CACHE_SIGNATURE_PASSPHRASE")
+                .ok_or(SignatureError::NoSignatureSecretKey)?
+                .into_raw_vec();
+            Ok::<_, SignatureError>(derive_key_from_passphrase(&passphrase, &self.team_id))
+        })?;
+        Ok(secret_key.clone())
     }
 
     fn construct_metadata(&self, hash: &[u8]) -> Result<Vec<u8>, SignatureError> {
@@ -59,6 +215,23 @@ impl ArtifactSignatureAuthenticator {
         Ok(metadata)
     }
 
+    // Unlike the legacy metadata, every field is length prefixed so that no
+    // two different sets of fields produce the same bytes.
+    fn construct_framed_metadata(
+        &self,
+        hash: &[u8],
+        body_len: usize,
+        compression: ArtifactCompression,
+    ) -> Vec<u8> {
+        let mut metadata = Vec::new();
+        for field in [hash, &self.team_id, compression.as_str().as_bytes()] {
+            metadata.extend_from_slice(&(field.len() as u64).to_be_bytes());
+            metadata.extend_from_slice(field);
+        }
+        metadata.extend_from_slice(&(body_len as u64).to_be_bytes());
+        metadata
+    }
+
     fn get_tag_generator(&self, hash: &[u8]) -> Result<hmac::Context, SignatureError> {
         let secret_key = hmac::Key::new(TURBO_HMAC_ALGORITHM, &self.secret_key()?);
         let metadata = self.construct_metadata(hash)?;
@@ -93,18 +266,216 @@ impl ArtifactSignatureAuthenticator {
         Ok(BASE64_STANDARD.encode(hmac_output))
     }
 
+    /// Generates a tag that also signs the length of `artifact_body` and its
+    /// declared compression. Framed tags are prefixed with `v2:`, and can
+    /// only be checked with `validate_framed`.
+    pub fn generate_framed_tag(
+        &self,
+        hash: &[u8],
+        artifact_body: &[u8],
+        compression: ArtifactCompression,
+    ) -> Result<String, SignatureError> {
+        let secret_key = hmac::Key::new(TURBO_HMAC_ALGORITHM, &self.secret_key()?);
+        let mut hmac_ctx = hmac::Context::with_key(&secret_key);
+        hmac_ctx.update(&self.construct_framed_metadata(hash, artifact_body.len(), compression));
+        hmac_ctx.update(artifact_body);
+        let hmac_output = hmac_ctx.sign();
+        Ok(format!(
+            "{FRAMED_TAG_PREFIX}{}",
+            BASE64_STANDARD.encode(hmac_output)
+        ))
+    }
+
+    /// Checks a tag for an artifact whose body is declared to use
+    /// `compression`. Framed tags must match the body length and compression
+    /// as well as the contents. Legacy tags are checked with `validate`
+    /// unless they were disabled with `with_legacy_tags(false)`.
+    pub fn validate_framed(
+        &self,
+        hash: &[u8],
+        artifact_body: &[u8],
+        compression: ArtifactCompression,
+        expected_tag: &str,
+    ) -> Result<bool, SignatureError> {
+        let valid = match expected_tag.strip_prefix(FRAMED_TAG_PREFIX) {
+            Some(framed_tag) => {
+                let secret_key = hmac::Key::new(TURBO_HMAC_ALGORITHM, &self.secret_key()?);
+                let mut message =
+                    self.construct_framed_metadata(hash, artifact_body.len(), compression);
+                message.extend(artifact_body);
+                let (expected_bytes, well_formed) = decode_tag(framed_tag.as_bytes());
+                let matches = hmac::verify(&secret_key, &message, &expected_bytes).is_ok();
+                well_formed & matches
+            }
+            None if self.allow_legacy_tags => {
+                self.matches_legacy_tag(hash, artifact_body, expected_tag)?
+            }
+            None => false,
+        };
+        if !valid {
+            self.record_failure(hash, artifact_body, compression, expected_tag);
+        }
+        Ok(valid)
+    }
+
+    /// Checks `expected_tag` against the tag for this artifact.
+    ///
+    /// A tag that isn't valid base64 or has the wrong length is reported the
+    /// same way as a tag that doesn't match, as `Ok(false)`. Decoding and
+    /// comparison take the same time regardless of where the tag is wrong, so
+    /// neither the result nor its timing reveals how close a forged tag is.
+    /// Errors are only returned for problems on our side, such as a missing
+    /// secret key.
     pub fn validate(
         &self,
         hash: &[u8],
         artifact_body: &[u8],
         expected_tag: &str,
+    ) -> Result<bool, SignatureError> {
+        let valid = self.matches_legacy_tag(hash, artifact_body, expected_tag)?;
+        if !valid {
+            self.record_failure(hash, artifact_body, ArtifactCompression::None, expected_tag);
+        }
+        Ok(valid)
+    }
+
+    fn matches_legacy_tag(
+        &self,
+        hash: &[u8],
+        artifact_body: &[u8],
+        expected_tag: &str,
     ) -> Result<bool, SignatureError> {
         let secret_key = hmac::Key::new(TURBO_HMAC_ALGORITHM, &self.secret_key()?);
         let mut message = self.construct_metadata(hash)?;
         message.extend(artifact_body);
-        let expected_bytes = BASE64_STANDARD.decode(expected_tag)?;
-        Ok(hmac::verify(&secret_key, &message, &expected_bytes).is_ok())
+        let (expected_bytes, well_formed) = decode_tag(expected_tag.as_bytes());
+        let matches = hmac::verify(&secret_key, &message, &expected_bytes).is_ok();
+        // Non short-circuiting so both checks always run
+        Ok(well_formed & matches)
     }
+
+    /// Gathers `SignatureDiagnostics` for an artifact whose tag didn't
+    /// validate. `compression` is only used for framed tags.
+    pub fn diagnose(
+        &self,
+        hash: &[u8],
+        artifact_body: &[u8],
+        compression: ArtifactCompression,
+        expected_tag: &str,
+    ) -> Result<SignatureDiagnostics, SignatureError> {
+        let tag_prefix = |tag: &str| -> String {
+            tag.trim_start_matches(FRAMED_TAG_PREFIX)
+                .chars()
+                .take(DIAGNOSTIC_TAG_PREFIX_LEN)
+                .collect()
+        };
+
+        let framed = expected_tag.starts_with(FRAMED_TAG_PREFIX);
+        let mut metadata = json!({
+            "hash": String::from_utf8_lossy(hash),
+            "teamId": String::from_utf8_lossy(&self.team_id),
+        });
+        let computed_tag = if framed {
+            metadata["compression"] = compression.as_str().into();
+            self.generate_framed_tag(hash, artifact_body, compression)?
+        } else {
+            self.generate_tag(hash, artifact_body)?
+        };
+        let key_id = hmac::sign(
+            &hmac::Key::new(TURBO_HMAC_ALGORITHM, &self.secret_key()?),
+            KEY_ID_DOMAIN,
+        );
+
+        Ok(SignatureDiagnostics {
+            framed,
+            expected_tag_prefix: tag_prefix(expected_tag),
+            computed_tag_prefix: tag_prefix(&computed_tag),
+            metadata,
+            key_id: to_hex(&key_id.as_ref()[..KEY_ID_LEN]),
+            body_len: artifact_body.len(),
+            body_sha256: to_hex(digest::digest(&digest::SHA256, artifact_body).as_ref()),
+        })
+    }
+
+    // Diagnostics are opt-in extra information, so failing to write them
+    // doesn't change the validation result
+    fn record_failure(
+        &self,
+        hash: &[u8],
+        artifact_body: &[u8],
+        compression: ArtifactCompression,
+        expected_tag: &str,
+    ) {
+        let Some(path) = &self.diagnostics_file else {
+            return;
+        };
+        let result = self
+            .diagnose(hash, artifact_body, compression, expected_tag)
+            .and_then(|diagnostics| diagnostics.write_to(path));
+        if let Err(err) = result {
+            warn!(
+                "failed to write signature diagnostics to {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Decodes a padded base64 tag without branching or indexing on its
+/// contents. Returns the decoded bytes along with whether the input was a
+/// well-formed, canonical encoding of a tag. The bytes should be ignored if
+/// it wasn't.
+fn decode_tag(encoded: &[u8]) -> ([u8; TURBO_TAG_LEN], bool) {
+    let mut tag = [0u8; TURBO_TAG_LEN];
+    // The length of the input isn't secret, so it's fine to bail out on it.
+    if encoded.len() != TURBO_ENCODED_TAG_LEN {
+        return (tag, false);
+    }
+
+    let (chars, padding) = encoded.split_at(TURBO_ENCODED_TAG_LEN - 1);
+    let mut invalid = 0u8;
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut out = 0;
+    for &c in chars {
+        let value = decode_char(c);
+        invalid |= (value >> 8) as u8;
+        acc = (acc << 6) | u32::from(value as u8 & 0x3f);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            // 43 characters hold 258 bits, so the final 2 bits never make it in here
+            if out < TURBO_TAG_LEN {
+                tag[out] = (acc >> bits) as u8;
+                out += 1;
+            }
+        }
+    }
+    // Canonical encodings leave the 2 trailing bits unset
+    invalid |= (acc & 0b11) as u8;
+    invalid |= padding[0] ^ b'=';
+
+    (tag, invalid == 0)
+}
+
+/// Maps a standard base64 character to its 6 bit value in constant time.
+/// Invalid characters produce a value with the high byte set.
+fn decode_char(c: u8) -> u16 {
+    let c = i16::from(c);
+    // Each range check evaluates to all ones if `c` is in the range and zero
+    // otherwise, selecting the offset that maps `c` to its value plus one.
+    let mut value: i16 = -1;
+    value += (((b'A' as i16 - 1 - c) & (c - (b'Z' as i16 + 1))) >> 8) & (c - 64);
+    value += (((b'a' as i16 - 1 - c) & (c - (b'z' as i16 + 1))) >> 8) & (c - 70);
+    value += (((b'0' as i16 - 1 - c) & (c - (b'9' as i16 + 1))) >> 8) & (c + 5);
+    value += (((b'+' as i16 - 1 - c) & (c - (b'+' as i16 + 1))) >> 8) & 63;
+    value += (((b'/' as i16 - 1 - c) & (c - (b'/' as i16 + 1))) >> 8) & 64;
+    value as u16
 }
 
 #[cfg(test)]
@@ -238,12 +609,223 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_derived_keys() -> Result<()> {
+        let team_id = b"tH7sL1Rn9K";
+        let key = derive_key_from_passphrase(b"correct horse battery staple", team_id);
+        assert_eq!(key.len(), TURBO_DERIVED_KEY_LEN);
+
+        // Derivation is deterministic
+        assert_eq!(
+            key,
+            derive_key_from_passphrase(b"correct horse battery staple", team_id)
+        );
+        // but depends on both the passphrase and the team
+        assert_ne!(
+            key,
+            derive_key_from_passphrase(b"correct horse battery stapler", team_id)
+        );
+        assert_ne!(
+            key,
+            derive_key_from_passphrase(b"correct horse battery staple", b"sL2vM9Qj1D")
+        );
+
+        let hash = b"d5b7e4688f";
+        let artifact_body = &[5, 72, 219, 39, 156];
+        let signer =
+            ArtifactSignatureAuthenticator::from_passphrase(team_id.to_vec(), b"correct horse");
+        let tag = signer.generate_tag(hash, artifact_body)?;
+
+        let verifier = ArtifactSignatureAuthenticator::new(
+            team_id.to_vec(),
+            Some(derive_key_from_passphrase(b"correct horse", team_id)),
+        );
+        assert!(verifier.validate(hash, artifact_body, &tag)?);
+
+        let wrong_passphrase =
+            ArtifactSignatureAuthenticator::from_passphrase(team_id.to_vec(), b"wrong horse");
+        assert!(!wrong_passphrase.validate(hash, artifact_body, &tag)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_char() {
+        let alphabet = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        for c in 0..=u8::MAX {
+            let expected = alphabet.iter().position(|&a| a == c);
+            let value = decode_char(c);
+            match expected {
+                Some(expected) => assert_eq!(value, expected as u16, "{:?}", c as char),
+                None => assert_ne!(value >> 8, 0, "{:?}", c as char),
+            }
+        }
+    }
+
+    #[test]
+    fn test_malformed_tags_are_invalid() -> Result<()> {
+        let signer = ArtifactSignatureAuthenticator::new(b"team".to_vec(), Some(b"key".to_vec()));
+        let hash = b"d5b7e4688f";
+        let artifact_body = &[5, 72, 219, 39, 156];
+        let tag = signer.generate_tag(hash, artifact_body)?;
+        assert_eq!(tag.len(), TURBO_ENCODED_TAG_LEN);
+        assert_eq!(
+            decode_tag(tag.as_bytes()),
+            (BASE64_STANDARD.decode(&tag)?.try_into().unwrap(), true)
+        );
+        assert!(signer.validate(hash, artifact_body, &tag)?);
+
+        let (body, _) = tag.split_at(TURBO_ENCODED_TAG_LEN - 2);
+        let last = tag.as_bytes()[TURBO_ENCODED_TAG_LEN - 2];
+        // Nudges the last character so the 2 trailing bits are no longer zero
+        let non_canonical = format!("{}{}=", body, (last ^ 1) as char);
+        let malformed = [
+            String::new(),
+            "=".repeat(TURBO_ENCODED_TAG_LEN),
+            tag[..TURBO_ENCODED_TAG_LEN - 1].to_string(),
+            tag[..TURBO_ENCODED_TAG_LEN - 4].to_string(),
+            format!("{tag}A"),
+            format!("{}A", &tag[..TURBO_ENCODED_TAG_LEN - 1]),
+            format!("!{}", &tag[1..]),
+            format!("{}!=", body),
+            tag.replace('+', "-").replace('/', "_"),
+            format!(" {}", &tag[1..]),
+            non_canonical,
+            BASE64_STANDARD.encode([0u8; TURBO_TAG_LEN - 1]),
+            BASE64_STANDARD.encode([0u8; TURBO_TAG_LEN + 1]),
+        ];
+        for bad_tag in malformed {
+            if bad_tag == tag {
+                continue;
+            }
+            assert!(
+                !decode_tag(bad_tag.as_bytes()).1,
+                "{bad_tag:?} decoded as well formed"
+            );
+            assert!(
+                !signer.validate(hash, artifact_body, &bad_tag)?,
+                "{bad_tag:?} was accepted"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_framed_tags() -> Result<()> {
+        let signer = ArtifactSignatureAuthenticator::new(b"team".to_vec(), Some(b"key".to_vec()));
+        let hash = b"d5b7e4688f";
+        let artifact_body = &[5, 72, 219, 39, 156];
+        let tag = signer.generate_framed_tag(hash, artifact_body, ArtifactCompression::Zstd)?;
+        assert!(tag.starts_with(FRAMED_TAG_PREFIX));
+        assert!(signer.validate_framed(hash, artifact_body, ArtifactCompression::Zstd, &tag)?);
+
+        // The same bytes declared with a different compression are rejected
+        assert!(!signer.validate_framed(hash, artifact_body, ArtifactCompression::Gzip, &tag)?);
+        // as is a body that only shares a prefix with the signed one
+        assert!(!signer.validate_framed(
+            hash,
+            &artifact_body[..4],
+            ArtifactCompression::Zstd,
+            &tag
+        )?);
+        // and framed tags can't be passed off as legacy ones
+        assert!(!signer.validate(hash, artifact_body, &tag)?);
+
+        // Legacy tags are accepted until they're disabled
+        let legacy_tag = signer.generate_tag(hash, artifact_body)?;
+        assert!(signer.validate_framed(
+            hash,
+            artifact_body,
+            ArtifactCompression::Zstd,
+            &legacy_tag
+        )?);
+        let strict = signer.with_legacy_tags(false);
+        assert!(!strict.validate_framed(
+            hash,
+            artifact_body,
+            ArtifactCompression::Zstd,
+            &legacy_tag
+        )?);
+        assert!(strict.validate_framed(hash, artifact_body, ArtifactCompression::Zstd, &tag)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diagnostics() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let diagnostics_file = dir.path().join("signature-diagnostics.jsonl");
+        let hash = b"d5b7e4688f";
+        let artifact_body = &[5, 72, 219, 39, 156];
+
+        let uploader = ArtifactSignatureAuthenticator::new(b"team".to_vec(), Some(b"key".to_vec()));
+        let tag = uploader.generate_tag(hash, artifact_body)?;
+        let framed_tag =
+            uploader.generate_framed_tag(hash, artifact_body, ArtifactCompression::Zstd)?;
+
+        // Successful validation writes nothing
+        let downloader =
+            ArtifactSignatureAuthenticator::new(b"team".to_vec(), Some(b"key".to_vec()))
+                .with_diagnostics_file(&diagnostics_file);
+        assert!(downloader.validate(hash, artifact_body, &tag)?);
+        assert!(!diagnostics_file.exists());
+
+        // A corrupted body, with the same key
+        assert!(!downloader.validate(hash, &artifact_body[..4], &tag)?);
+        // and the right body with a different key
+        let wrong_key =
+            ArtifactSignatureAuthenticator::new(b"team".to_vec(), Some(b"other".to_vec()))
+                .with_diagnostics_file(&diagnostics_file);
+        assert!(!wrong_key.validate_framed(
+            hash,
+            artifact_body,
+            ArtifactCompression::Zstd,
+            &framed_tag
+        )?);
+
+        let contents = std::fs::read_to_string(&diagnostics_file)?;
+        let entries: Vec<serde_json::Value> = contents
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(entries.len(), 2);
+        let (corrupted, mismatched) = (&entries[0], &entries[1]);
+
+        let expected = uploader.diagnose(hash, artifact_body, ArtifactCompression::Zstd, &tag)?;
+        assert_eq!(corrupted["keyId"], expected.key_id);
+        // The key id doesn't reveal a plain digest of the key
+        assert_eq!(expected.key_id.len(), KEY_ID_LEN * 2);
+        assert_ne!(
+            expected.key_id,
+            to_hex(&digest::digest(&digest::SHA256, b"key").as_ref()[..KEY_ID_LEN])
+        );
+        assert_ne!(corrupted["bodySha256"], expected.body_sha256);
+        assert_eq!(corrupted["bodyLen"], 4);
+        assert_eq!(
+            corrupted["expectedTagPrefix"],
+            &tag[..DIAGNOSTIC_TAG_PREFIX_LEN]
+        );
+        assert_eq!(corrupted["framed"], false);
+        assert_eq!(corrupted["metadata"]["teamId"], "team");
+
+        assert_ne!(mismatched["keyId"], expected.key_id);
+        assert_eq!(mismatched["bodySha256"], expected.body_sha256);
+        assert_eq!(mismatched["framed"], true);
+        assert_eq!(mismatched["metadata"]["compression"], "zstd");
+        // Neither the key nor a whole tag ends up in the file
+        assert!(!contents.contains(&tag));
+        assert!(!contents.contains("\"other\""));
+
+        // Failing to write diagnostics doesn't turn a failed check into an
+        // error
+        let unwritable =
+            ArtifactSignatureAuthenticator::new(b"team".to_vec(), Some(b"key".to_vec()))
+                .with_diagnostics_file(dir.path());
+        assert!(!unwritable.validate(hash, &artifact_body[..4], &tag)?);
+        Ok(())
+    }
+
     fn test_signature(test_case: TestCase) -> Result<()> {
         env::set_var("TURBO_REMOTE_CACHE_SIGNATURE_KEY", test_case.secret_key);
-        let signature = ArtifactSignatureAuthenticator {
-            team_id: test_case.team_id.to_vec(),
-            secret_key_override: None,
-        };
+        let signature = ArtifactSignatureAuthenticator::new(test_case.team_id.to_vec(), None);
 
         let hash = test_case.artifact_hash;
         let artifact_body = &test_case.artifact_body;
@@ -0,0 +1,263 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+use thiserror::Error;
+use turbopath::{AbsoluteSystemPath, AnchoredSystemPathBuf, PathError};
+use walkdir::WalkDir;
+
+use crate::hash::HashAlgorithm;
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error(transparent)]
+    Path(#[from] PathError),
+    #[error("failed to read {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("failed to walk directory: {0}")]
+    Walk(#[from] walkdir::Error),
+}
+
+/// What a path in a snapshot pointed to when the snapshot was taken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotEntry {
+    /// A regular file with the hex encoded hash of its contents, computed
+    /// with the snapshot's `HashAlgorithm`
+    File { hash: String, size: u64 },
+    /// A symlink and its (unresolved) target
+    Symlink { target: PathBuf },
+}
+
+/// A content manifest of the files matched by a set of globs, keyed by path
+/// relative to the anchor the snapshot was taken in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    algorithm: HashAlgorithm,
+    entries: BTreeMap<AnchoredSystemPathBuf, SnapshotEntry>,
+}
+
+impl Snapshot {
+    /// The algorithm file contents were hashed with
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    pub fn get(&self, path: &AnchoredSystemPathBuf) -> Option<&SnapshotEntry> {
+        self.entries.get(path)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&AnchoredSystemPathBuf, &SnapshotEntry)> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// The difference between two snapshots. Paths are sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    pub added: Vec<AnchoredSystemPathBuf>,
+    pub removed: Vec<AnchoredSystemPathBuf>,
+    pub changed: Vec<AnchoredSystemPathBuf>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Records the contents of every file and symlink under `anchor` that
+/// matches `globs`. Globs are unix-style and relative to `anchor`; a glob
+/// starting with `!` excludes paths matched by the remaining pattern.
+/// Directories are walked but not recorded, and symlinks are not followed.
+/// File contents are hashed with SHA-256.
+pub fn snapshot(anchor: &AbsoluteSystemPath, globs: &[&str]) -> Result<Snapshot, SnapshotError> {
+    snapshot_with_algorithm(anchor, globs, HashAlgorithm::default())
+}
+
+/// Like `snapshot`, with file contents hashed using `algorithm`.
+pub fn snapshot_with_algorithm(
+    anchor: &AbsoluteSystemPath,
+    globs: &[&str],
+    algorithm: HashAlgorithm,
+) -> Result<Snapshot, SnapshotError> {
+    let (exclusions, inclusions): (Vec<&str>, Vec<&str>) =
+        globs.iter().partition(|glob| glob.starts_with('!'));
+    let exclusions: Vec<&str> = exclusions.iter().map(|glob| &glob[1..]).collect();
+
+    let mut entries = BTreeMap::new();
+    for entry in WalkDir::new(anchor.as_path()).follow_links(false) {
+        let entry = entry?;
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            continue;
+        }
+
+        let path = AbsoluteSystemPath::new(entry.path())?;
+        let anchored = anchor.anchor(path)?;
+        let unix_path = anchored.to_unix()?;
+        let unix_path = unix_path.as_str()?;
+        let is_match = |glob: &&str| glob_match::glob_match(glob, unix_path);
+        if !inclusions.iter().any(is_match) || exclusions.iter().any(is_match) {
+            continue;
+        }
+
+        let snapshot_entry = if file_type.is_symlink() {
+            let target = path.read_link().map_err(|source| SnapshotError::Io {
+                path: entry.path().to_owned(),
+                source,
+            })?;
+            SnapshotEntry::Symlink { target }
+        } else {
+            hash_file(path, algorithm)?
+        };
+        entries.insert(anchored, snapshot_entry);
+    }
+
+    Ok(Snapshot { algorithm, entries })
+}
+
+fn hash_file(
+    path: &AbsoluteSystemPath,
+    algorithm: HashAlgorithm,
+) -> Result<SnapshotEntry, SnapshotError> {
+    let to_error = |source| SnapshotError::Io {
+        path: path.as_path().to_owned(),
+        source,
+    };
+    let mut file = fs::File::open(path.as_path()).map_err(to_error)?;
+    let mut hasher = algorithm.hasher();
+    let mut buffer = [0; 8192];
+    let mut size = 0;
+    loop {
+        let read = file.read(&mut buffer).map_err(to_error)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+
+    Ok(SnapshotEntry::File {
+        hash: hasher.finish(),
+        size,
+    })
+}
+
+/// Compares two snapshots of the same outputs, returning which paths only
+/// exist in `after`, which only exist in `before`, and which exist in both
+/// with different contents. Both snapshots should use the same
+/// `HashAlgorithm`, otherwise every file present in both is reported as
+/// changed.
+pub fn diff_snapshots(before: &Snapshot, after: &Snapshot) -> SnapshotDiff {
+    let mut diff = SnapshotDiff::default();
+    for (path, entry) in &before.entries {
+        match after.entries.get(path) {
+            None => diff.removed.push(path.clone()),
+            Some(other) if other != entry => diff.changed.push(path.clone()),
+            Some(_) => {}
+        }
+    }
+    diff.added = after
+        .entries
+        .keys()
+        .filter(|path| !before.entries.contains_key(*path))
+        .cloned()
+        .collect();
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use anyhow::Result;
+    use tempfile::tempdir;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::*;
+
+    fn anchored(path: &str) -> AnchoredSystemPathBuf {
+        Path::new(path).try_into().unwrap()
+    }
+
+    #[test]
+    fn test_snapshot_matches_globs() -> Result<()> {
+        let dir = tempdir()?;
+        let anchor = AbsoluteSystemPathBuf::new(dir.path())?;
+        fs::create_dir_all(dir.path().join("dist/nested"))?;
+        fs::write(dir.path().join("dist/index.js"), "index")?;
+        fs::write(dir.path().join("dist/index.js.map"), "map")?;
+        fs::write(dir.path().join("dist/nested/a.js"), "a")?;
+        fs::write(dir.path().join("src.js"), "src")?;
+
+        let snapshot = snapshot(anchor.as_absolute_path(), &["dist/**", "!dist/**/*.map"])?;
+        let paths: Vec<_> = snapshot.iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![anchored("dist/index.js"), anchored("dist/nested/a.js")]
+        );
+        assert_eq!(
+            snapshot.get(&anchored("dist/nested/a.js")),
+            Some(&SnapshotEntry::File {
+                hash: "d24ec4f1a98c6e5b".to_string(),
+                size: 1,
+            })
+        );
+        assert_eq!(snapshot.algorithm(), HashAlgorithm::Xxhash64);
+
+        let snapshot = snapshot_with_algorithm(
+            anchor.as_absolute_path(),
+            &["dist/nested/*"],
+            HashAlgorithm::Sha256,
+        )?;
+        assert_eq!(snapshot.algorithm(), HashAlgorithm::Sha256);
+        assert_eq!(
+            snapshot.get(&anchored("dist/nested/a.js")),
+            Some(&SnapshotEntry::File {
+                hash: HashAlgorithm::Sha256.hash(b"a"),
+                size: 1,
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_snapshots() -> Result<()> {
+        let dir = tempdir()?;
+        let anchor = AbsoluteSystemPathBuf::new(dir.path())?;
+        fs::write(dir.path().join("unchanged"), "same")?;
+        fs::write(dir.path().join("changed"), "before")?;
+        fs::write(dir.path().join("removed"), "gone soon")?;
+        let before = snapshot(anchor.as_absolute_path(), &["*"])?;
+
+        fs::write(dir.path().join("changed"), "after")?;
+        fs::remove_file(dir.path().join("removed"))?;
+        fs::write(dir.path().join("added"), "new")?;
+        let after = snapshot(anchor.as_absolute_path(), &["*"])?;
+
+        assert_eq!(
+            diff_snapshots(&before, &after),
+            SnapshotDiff {
+                added: vec![anchored("added")],
+                removed: vec![anchored("removed")],
+                changed: vec![anchored("changed")],
+            }
+        );
+        assert!(diff_snapshots(&after, &after).is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+pub(crate) use turborepo_api_client::test_utils::{RecordedRequest, TestResponse, TestServer};
+
+use crate::{Cache, CacheError};
+
+/// An in-memory `Cache` that counts the requests made to it, for testing
+/// wrappers.
+#[derive(Default)]
+pub(crate) struct MemoryCache {
+    pub(crate) objects: Mutex<HashMap<String, Bytes>>,
+    requests: AtomicUsize,
+}
+
+impl MemoryCache {
+    pub(crate) fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl Cache for MemoryCache {
+    async fn fetch(&self, hash: &str) -> Result<Option<Bytes>, CacheError> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        Ok(self.objects.lock().unwrap().get(hash).cloned())
+    }
+
+    async fn put(&self, hash: &str, body: Bytes) -> Result<(), CacheError> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        self.objects.lock().unwrap().insert(hash.to_string(), body);
+        Ok(())
+    }
+
+    async fn exists(&self, hash: &str) -> Result<bool, CacheError> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        Ok(self.objects.lock().unwrap().contains_key(hash))
+    }
+}
+
+/// Answers `TestServer` requests like a bare bones object store, e.g. nginx
+/// with WebDAV: `PUT` stores the body under the request path, and `GET` and
+/// `HEAD` look it up.
+#[derive(Default, Clone)]
+pub(crate) struct ObjectStore {
+    objects: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+impl ObjectStore {
+    pub(crate) fn respond(&self, request: &RecordedRequest) -> TestResponse {
+        let mut objects = self.objects.lock().unwrap();
+        match request.method.as_str() {
+            "PUT" => {
+                objects.insert(request.path.clone(), request.body.clone());
+                TestResponse::new("200 OK")
+            }
+            "GET" | "HEAD" => match objects.get(&request.path) {
+                Some(object) => TestResponse::new("200 OK").body(object.clone()),
+                None => TestResponse::new("404 Not Found"),
+            },
+            _ => TestResponse::new("405 Method Not Allowed"),
+        }
+    }
+
+    /// Stored objects, keyed by path
+    pub(crate) fn objects(&self) -> MutexGuard<'_, HashMap<String, Vec<u8>>> {
+        self.objects.lock().unwrap()
+    }
+}
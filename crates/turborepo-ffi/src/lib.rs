@@ -4,15 +4,47 @@
 //! and in ffi.go before modifying this file.
 mod lockfile;
 
-use std::{mem::ManuallyDrop, path::PathBuf};
+use std::path::PathBuf;
 
 pub use lockfile::{patches, subgraph, transitive_closure};
 use turbopath::AbsoluteSystemPathBuf;
+use turborepo_cache::signature_authentication::ArtifactCompression;
 
 mod proto {
     include!(concat!(env!("OUT_DIR"), "/_.rs"));
 }
 
+/// The version of `messages.proto`. Bump this whenever a message changes
+/// in a way the Go side needs to know about.
+const PROTO_SCHEMA_VERSION: u32 = 1;
+
+/// Features the Go side can gate on via `capabilities`, besides one
+/// `compression:<format>` feature per `COMPRESSION_FORMATS` entry
+const FEATURES: &[&str] = &[
+    // The API client can send CORS preflight requests before artifact
+    // requests, see `APIClient::set_use_preflight`
+    "preflight",
+    // `generate_tag` and `verify_signature`
+    "signing",
+];
+
+/// The artifact compression formats that signatures can be bound to
+const COMPRESSION_FORMATS: &[ArtifactCompression] = &[
+    ArtifactCompression::None,
+    ArtifactCompression::Gzip,
+    ArtifactCompression::Zstd,
+];
+
+/// A byte buffer passed across the FFI boundary.
+///
+/// Ownership depends on which side allocated the buffer:
+///
+/// - Buffers passed *into* an exported function (requests) are allocated by Go
+///   and remain owned by Go. Rust only reads them and never frees them; Go
+///   releases them with `Buffer.Free`.
+/// - Buffers returned *from* an exported function (responses) are allocated by
+///   Rust and ownership passes to Go. Go must hand each of them back exactly
+///   once to `free_buffer`.
 #[repr(C)]
 #[derive(Debug)]
 pub struct Buffer {
@@ -20,26 +52,44 @@ pub struct Buffer {
     data: *mut u8,
 }
 
+/// Frees a buffer that was returned by one of the exported functions.
+///
+/// Calling this with a buffer that was allocated on the Go side, or calling
+/// it twice with the same buffer, is undefined behaviour. A buffer with a null
+/// data pointer is ignored.
 #[no_mangle]
 pub extern "C" fn free_buffer(buffer: Buffer) {
+    if buffer.data.is_null() {
+        return;
+    }
     // SAFETY
     // it is important that any memory allocated in rust, is freed in rust
-    // we do this by converting the raw pointer to a Vec and letting it drop
-    // this is safe because we know that the memory was allocated by rust
-    // and that the length is correct
-    let _ = unsafe { Vec::from_raw_parts(buffer.data, buffer.len as usize, buffer.len as usize) };
+    // we do this by converting the raw pointer back into the boxed slice it
+    // was created from and letting it drop. this is safe because we know that
+    // the memory was allocated by rust as a boxed slice, so the length and
+    // capacity of the allocation are both `len`
+    let _ = unsafe {
+        Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            buffer.data,
+            buffer.len as usize,
+        ))
+    };
 }
 
 impl<T: prost::Message> From<T> for Buffer {
     fn from(value: T) -> Self {
-        let mut bytes = ManuallyDrop::new(value.encode_to_vec());
-        let data = bytes.as_mut_ptr();
+        // converting to a boxed slice drops any excess capacity, so that
+        // `free_buffer` can reconstruct the allocation from `len` alone
+        let bytes = value.encode_to_vec().into_boxed_slice();
         let len = bytes.len() as u32;
+        let data = Box::into_raw(bytes) as *mut u8;
         Buffer { len, data }
     }
 }
 
 impl Buffer {
+    /// Decodes a request buffer. This borrows the underlying memory, which
+    /// is still owned (and freed) by the Go side.
     #[allow(dead_code)]
     fn into_proto<T: prost::Message + Default>(self) -> Result<T, prost::DecodeError> {
         // SAFETY
@@ -63,6 +113,27 @@ pub extern "C" fn get_turbo_data_dir() -> Buffer {
     proto::TurboDataDirResp { dir }.into()
 }
 
+#[no_mangle]
+pub extern "C" fn capabilities() -> Buffer {
+    proto::CapabilitiesResponse {
+        schema_version: PROTO_SCHEMA_VERSION,
+        features: FEATURES
+            .iter()
+            .map(|feature| feature.to_string())
+            .chain(
+                COMPRESSION_FORMATS
+                    .iter()
+                    .map(|format| format!("compression:{}", format.as_str())),
+            )
+            .collect(),
+        library_version: env!("CARGO_PKG_VERSION").to_string(),
+        target_os: std::env::consts::OS.to_string(),
+        target_arch: std::env::consts::ARCH.to_string(),
+        debug_build: cfg!(debug_assertions),
+    }
+    .into()
+}
+
 #[no_mangle]
 pub extern "C" fn changed_files(buffer: Buffer) -> Buffer {
     let req: proto::ChangedFilesReq = match buffer.into_proto() {
@@ -203,3 +274,109 @@ pub extern "C" fn verify_signature(buffer: Buffer) -> Buffer {
         }
     }
 }
+
+#[no_mangle]
+pub extern "C" fn generate_tag(buffer: Buffer) -> Buffer {
+    let req: proto::GenerateTagRequest = match buffer.into_proto() {
+        Ok(req) => req,
+        Err(err) => {
+            let resp = proto::GenerateTagResponse {
+                response: Some(proto::generate_tag_response::Response::Error(
+                    err.to_string(),
+                )),
+            };
+            return resp.into();
+        }
+    };
+
+    let authenticator =
+        turborepo_cache::signature_authentication::ArtifactSignatureAuthenticator::new(
+            req.team_id,
+            req.secret_key_override,
+        );
+
+    let response = match authenticator.generate_tag(req.hash.as_bytes(), &req.artifact_body) {
+        Ok(tag) => proto::generate_tag_response::Response::Tag(tag),
+        Err(err) => proto::generate_tag_response::Response::Error(err.to_string()),
+    };
+
+    let resp = proto::GenerateTagResponse {
+        response: Some(response),
+    };
+    resp.into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // These tests exercise allocation and deallocation of buffers, and are
+    // intended to be run under Miri or a leak sanitizer as well as normally:
+    //
+    //   cargo +nightly miri test -p turborepo-ffi
+    #[test]
+    fn test_response_buffer_round_trip() {
+        let buffer: Buffer = proto::TurboDataDirResp {
+            dir: "/some/dir".to_string(),
+        }
+        .into();
+
+        // simulate go reading the response and freeing it
+        let slice = unsafe { std::slice::from_raw_parts(buffer.data, buffer.len as usize) };
+        let resp = <proto::TurboDataDirResp as prost::Message>::decode(slice).unwrap();
+        assert_eq!(resp.dir, "/some/dir");
+        free_buffer(buffer);
+    }
+
+    #[test]
+    fn test_empty_response_buffer() {
+        let buffer: Buffer = proto::RecursiveCopyResponse { error: None }.into();
+        assert_eq!(buffer.len, 0);
+        free_buffer(buffer);
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let buffer = capabilities();
+        let slice = unsafe { std::slice::from_raw_parts(buffer.data, buffer.len as usize) };
+        let resp = <proto::CapabilitiesResponse as prost::Message>::decode(slice).unwrap();
+        free_buffer(buffer);
+
+        assert_eq!(resp.schema_version, PROTO_SCHEMA_VERSION);
+        assert_eq!(
+            resp.features,
+            [
+                "preflight",
+                "signing",
+                "compression:none",
+                "compression:gzip",
+                "compression:zstd"
+            ]
+        );
+        assert_eq!(resp.library_version, env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn test_free_null_buffer() {
+        free_buffer(Buffer {
+            len: 0,
+            data: std::ptr::null_mut(),
+        });
+    }
+
+    #[test]
+    fn test_request_buffer_is_not_freed() {
+        // request buffers are owned by the caller, so decoding one must leave
+        // the memory intact
+        let mut bytes = prost::Message::encode_to_vec(&proto::GlobalChangeResponse {
+            global_change: true,
+        });
+        let buffer = Buffer {
+            len: bytes.len() as u32,
+            data: bytes.as_mut_ptr(),
+        };
+        let resp: proto::GlobalChangeResponse = buffer.into_proto().unwrap();
+        assert!(resp.global_change);
+        assert_eq!(bytes.len(), 2);
+    }
+}
@@ -106,6 +106,10 @@ pub(crate) async fn verify_caching_enabled<'a>(
         CachingStatus::OverLimit => Err(anyhow!("usage limit")),
         CachingStatus::Paused => Err(anyhow!("spending paused")),
         CachingStatus::Enabled => Ok(()),
+        CachingStatus::Unknown(status) => Err(anyhow!(
+            "unrecognized caching status {:?}, try upgrading to the latest version of turbo",
+            status
+        )),
     }
 }
 
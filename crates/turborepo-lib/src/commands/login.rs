@@ -11,6 +11,7 @@ use tokio::sync::OnceCell;
 use tracing::debug;
 #[cfg(not(test))]
 use tracing::warn;
+use turborepo_api_client::{APIClient, VerifiedSsoUser};
 
 use crate::{
     commands::{
@@ -66,9 +67,22 @@ pub async fn sso_login(base: &mut CommandBase, sso_team: &str) -> Result<()> {
         )))
     );
 
-    if let Some(team_id) = verified_user.team_id {
-        verify_caching_enabled(&api_client, &team_id, &verified_user.token, None).await?;
-        base.repo_config_mut()?.set_team_id(Some(team_id))?;
+    link_sso_team(base, &api_client, &verified_user, sso_team).await
+}
+
+// Links the repo to the team the SSO login was for. Not being able to tell
+// which team that is isn't an error, since the user is already logged in and
+// can still link with `npx turbo link`.
+async fn link_sso_team(
+    base: &mut CommandBase,
+    api_client: &APIClient,
+    verified_user: &VerifiedSsoUser,
+    sso_team: &str,
+) -> Result<()> {
+    let linkage = api_client.select_team(verified_user, Some(sso_team)).await;
+    if let Some(team) = linkage.team {
+        verify_caching_enabled(api_client, &team.id, &linkage.token, None).await?;
+        base.repo_config_mut()?.set_team_id(Some(team.id))?;
         println!(
             "{}
 
@@ -305,6 +319,7 @@ mod test {
     use tempfile::NamedTempFile;
     use tokio::sync::OnceCell;
     use turbopath::AbsoluteSystemPathBuf;
+    use turborepo_api_client::VerifiedSsoUser;
     use vercel_api_mock::start_test_server;
 
     use crate::{
@@ -422,6 +437,66 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn test_sso_login_without_team() {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+
+        let user_config_file = NamedTempFile::new().unwrap();
+        fs::write(user_config_file.path(), "{}").unwrap();
+        let repo_config_file = NamedTempFile::new().unwrap();
+        let repo_config_path = AbsoluteSystemPathBuf::new(repo_config_file.path()).unwrap();
+        fs::write(repo_config_file.path(), "{}").unwrap();
+
+        let mut base = CommandBase {
+            repo_root: Default::default(),
+            ui: UI::new(false),
+            client_config: OnceCell::from(ClientConfigLoader::new().load().unwrap()),
+            user_config: OnceCell::from(
+                UserConfigLoader::new(user_config_file.path().to_path_buf())
+                    .load()
+                    .unwrap(),
+            ),
+            repo_config: OnceCell::from(
+                RepoConfigLoader::new(repo_config_path)
+                    .with_api(Some(format!("http://localhost:{}", port)))
+                    .load()
+                    .unwrap(),
+            ),
+            args: Args::default(),
+            version: "",
+        };
+        let api_client = base.api_client().unwrap();
+        let verified_user = VerifiedSsoUser {
+            token: vercel_api_mock::EXPECTED_TOKEN.to_string(),
+            team_id: None,
+            extra: Default::default(),
+        };
+
+        // The mock user isn't a member of this team, which leaves the repo
+        // unlinked instead of failing the login
+        login::link_sso_team(&mut base, &api_client, &verified_user, "unknown_team")
+            .await
+            .unwrap();
+        assert_eq!(base.repo_config().unwrap().team_id(), None);
+
+        login::link_sso_team(
+            &mut base,
+            &api_client,
+            &verified_user,
+            vercel_api_mock::EXPECTED_TEAM_SLUG,
+        )
+        .await
+        .unwrap();
+
+        handle.abort();
+
+        assert_eq!(
+            base.repo_config().unwrap().team_id().unwrap(),
+            vercel_api_mock::EXPECTED_TEAM_ID
+        );
+    }
+
     #[test]
     fn test_get_token_and_redirect() {
         assert_eq!(
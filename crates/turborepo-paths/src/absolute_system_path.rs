@@ -9,7 +9,9 @@ use std::{
     fmt, fs,
     fs::Metadata,
     io,
-    path::{Path, PathBuf},
+    num::NonZeroUsize,
+    path::{Component, Path, PathBuf},
+    thread,
 };
 
 use path_slash::CowExt;
@@ -19,6 +21,10 @@ use crate::{
     RelativeSystemPathBuf, RelativeUnixPath,
 };
 
+// Below this many paths, spawning threads costs more than the stats do
+const PARALLEL_STAT_THRESHOLD: usize = 64;
+
+#[repr(transparent)]
 pub struct AbsoluteSystemPath(Path);
 
 impl ToOwned for AbsoluteSystemPath {
@@ -128,6 +134,66 @@ impl AbsoluteSystemPath {
         AnchoredSystemPathBuf::new(self, path)
     }
 
+    /// Iterates over the paths between `anchor` and this path, starting with
+    /// the child of `anchor` and ending with this path. `anchor` itself is not
+    /// included, so if this path is `anchor` the iterator is empty. These are
+    /// the directories that need to exist, in order, for this path to be
+    /// created under `anchor`.
+    pub fn descendants_within(
+        &self,
+        anchor: &AbsoluteSystemPath,
+    ) -> Result<impl Iterator<Item = &AbsoluteSystemPath>, PathError> {
+        // `strip_prefix` compares components lexically, so `anchor/a/../..`
+        // would pass it while resolving to a path outside of `anchor`
+        let rest = match self.0.strip_prefix(&anchor.0) {
+            Ok(rest)
+                if !rest
+                    .components()
+                    .any(|c| matches!(c, Component::ParentDir | Component::CurDir)) =>
+            {
+                rest
+            }
+            _ => {
+                return Err(
+                    PathValidationError::NotParent(anchor.to_string(), self.to_string()).into(),
+                )
+            }
+        };
+        let depth = rest.components().count();
+        Ok((0..depth).rev().map(move |n| {
+            let ancestor = self
+                .0
+                .ancestors()
+                .nth(n)
+                .expect("path has fewer ancestors than components");
+            // SAFETY: every ancestor of an absolute system path is also one,
+            // and `AbsoluteSystemPath` is a `#[repr(transparent)]` `Path`
+            unsafe { &*(ancestor as *const Path as *const Self) }
+        }))
+    }
+
+    /// Creates the directories between `anchor` and this path, including this
+    /// path, like `fs::create_dir_all`. Unlike it, nothing under `anchor` is
+    /// followed if it isn't a directory and `..` is rejected, so neither a
+    /// symlink nor a parent component can be used to create directories
+    /// outside of `anchor`.
+    pub fn safe_mkdir_all(&self, anchor: &AbsoluteSystemPath) -> Result<(), PathError> {
+        for dir in self.descendants_within(anchor)? {
+            match dir.try_symlink_metadata()? {
+                Some(metadata) if metadata.is_dir() => {}
+                Some(_) => {
+                    return Err(PathError::io(
+                        "mkdir",
+                        &dir.0,
+                        io::Error::new(io::ErrorKind::AlreadyExists, "not a directory"),
+                    ))
+                }
+                None => fs::create_dir(&dir.0).map_err(|e| PathError::io("mkdir", &dir.0, e))?,
+            }
+        }
+        Ok(())
+    }
+
     pub fn ensure_dir(&self) -> Result<(), io::Error> {
         if let Some(parent) = self.0.parent() {
             fs::create_dir_all(parent)
@@ -139,14 +205,14 @@ impl AbsoluteSystemPath {
     pub fn symlink_to_file<P: AsRef<Path>>(&self, to: P) -> Result<(), PathError> {
         let system_path = to.as_ref();
         let system_path = system_path.into_system()?;
-        symlink_file(system_path, &self.0)?;
+        symlink_file(system_path, &self.0).map_err(|e| PathError::io("symlink", &self.0, e))?;
         Ok(())
     }
 
     pub fn symlink_to_dir<P: AsRef<Path>>(&self, to: P) -> Result<(), PathError> {
         let system_path = to.as_ref();
         let system_path = system_path.into_system()?;
-        symlink_dir(&system_path, &self.0)?;
+        symlink_dir(system_path, &self.0).map_err(|e| PathError::io("symlink", &self.0, e))?;
         Ok(())
     }
 
@@ -158,11 +224,53 @@ impl AbsoluteSystemPath {
     // note that this is *not* lstat. If this is a symlink, it
     // will return metadata for the target.
     pub fn stat(&self) -> Result<Metadata, PathError> {
-        Ok(fs::metadata(&self.0)?)
+        fs::metadata(&self.0).map_err(|e| PathError::io("stat", &self.0, e))
     }
 
     pub fn symlink_metadata(&self) -> Result<Metadata, PathError> {
-        Ok(fs::symlink_metadata(&self.0)?)
+        fs::symlink_metadata(&self.0).map_err(|e| PathError::io("lstat", &self.0, e))
+    }
+
+    /// Like `symlink_metadata`, but a missing path is `Ok(None)` rather than an
+    /// error.
+    pub fn try_symlink_metadata(&self) -> Result<Option<Metadata>, PathError> {
+        match self.symlink_metadata() {
+            Ok(metadata) => Ok(Some(metadata)),
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Runs `try_symlink_metadata` for every path, spreading the calls across
+    /// threads when there are enough of them to be worth it. Results are in
+    /// the same order as `paths`.
+    pub fn symlink_metadata_many<P>(paths: &[P]) -> Vec<Result<Option<Metadata>, PathError>>
+    where
+        P: AsRef<AbsoluteSystemPath> + Sync,
+    {
+        let lstat_all = |paths: &[P]| -> Vec<_> {
+            paths
+                .iter()
+                .map(|path| path.as_ref().try_symlink_metadata())
+                .collect()
+        };
+
+        let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        if threads == 1 || paths.len() < PARALLEL_STAT_THRESHOLD {
+            return lstat_all(paths);
+        }
+
+        let chunk_size = (paths.len() + threads - 1) / threads;
+        thread::scope(|scope| {
+            let handles: Vec<_> = paths
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(|| lstat_all(chunk)))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("lstat thread panicked"))
+                .collect()
+        })
     }
 
     pub fn read_link(&self) -> Result<PathBuf, io::Error> {
@@ -180,6 +288,123 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_io_error_context() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let missing = dir.path().join("missing");
+        let path = AbsoluteSystemPath::new(&missing)?;
+
+        let err = path.stat().unwrap_err();
+        assert!(err.is_not_found());
+        assert!(!err.is_permission_denied());
+        assert_eq!(err.path(), Some(missing.as_path()));
+        assert!(err.to_string().contains(&missing.display().to_string()));
+        assert!(err.to_string().starts_with("failed to stat"));
+
+        // Converted errors still work, without a path to report
+        let err = PathError::from(io::Error::from(io::ErrorKind::NotFound));
+        assert!(err.is_not_found());
+        assert_eq!(err.path(), None);
+        assert_eq!(err.to_string(), "failed to perform I/O: entity not found");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_symlink_metadata_many() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = AbsoluteSystemPath::new(dir.path())?;
+        let mut paths = Vec::new();
+        for i in 0..(PARALLEL_STAT_THRESHOLD * 2) {
+            let path = root.join_literal(&format!("file-{i}"));
+            // Leave every third path missing
+            if i % 3 != 0 {
+                fs::write(&path, "contents")?;
+            }
+            paths.push(path);
+        }
+
+        // Small enough to be serial, and large enough to be parallel
+        for paths in [&paths[..3], &paths[..]] {
+            let results = AbsoluteSystemPath::symlink_metadata_many(paths);
+            assert_eq!(results.len(), paths.len());
+            for (i, result) in results.into_iter().enumerate() {
+                assert_eq!(result?.is_some(), i % 3 != 0, "{}", paths[i]);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_descendants_within() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let anchor = AbsoluteSystemPath::new(dir.path())?;
+        let path = anchor.join_literal("a").join_literal("b").join_literal("c");
+
+        let descendants: Vec<_> = path
+            .as_absolute_path()
+            .descendants_within(anchor)?
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            descendants,
+            vec![
+                anchor.join_literal("a").to_string(),
+                anchor.join_literal("a").join_literal("b").to_string(),
+                path.to_string(),
+            ]
+        );
+
+        assert_eq!(anchor.descendants_within(anchor)?.count(), 0);
+        // A sibling that shares a string prefix is not within the anchor
+        let sibling = AbsoluteSystemPathBuf::new(format!("{}-sibling", anchor))?;
+        assert!(sibling
+            .as_absolute_path()
+            .descendants_within(anchor)
+            .is_err());
+        assert!(anchor.descendants_within(path.as_absolute_path()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_safe_mkdir_all() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let anchor = AbsoluteSystemPath::new(dir.path())?;
+        let path = anchor.join_literal("a").join_literal("b");
+
+        path.as_absolute_path().safe_mkdir_all(anchor)?;
+        assert!(path.as_absolute_path().stat()?.is_dir());
+        // Existing directories are fine
+        path.as_absolute_path().safe_mkdir_all(anchor)?;
+
+        let outside = tempfile::tempdir()?;
+        let link = anchor.join_literal("link");
+        link.symlink_to_dir(outside.path())?;
+        let escaped = link.join_literal("c");
+        assert!(escaped.as_absolute_path().safe_mkdir_all(anchor).is_err());
+        assert!(!outside.path().join("c").exists());
+
+        let nested = anchor.join_literal("nested");
+        let escaped = nested
+            .join_literal("a")
+            .join_literal("..")
+            .join_literal("..")
+            .join_literal("x");
+        assert!(matches!(
+            escaped
+                .as_absolute_path()
+                .safe_mkdir_all(nested.as_absolute_path()),
+            Err(PathError::PathValidationError(
+                PathValidationError::NotParent(..)
+            ))
+        ));
+        assert!(!anchor.join_literal("x").as_path().exists());
+
+        Ok(())
+    }
+
     #[test]
     fn test_create_absolute_path() -> Result<()> {
         #[cfg(unix)]
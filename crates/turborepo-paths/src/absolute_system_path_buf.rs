@@ -193,15 +193,21 @@ impl AbsoluteSystemPathBuf {
     }
 
     pub fn set_readonly(&self) -> Result<(), PathError> {
-        let metadata = fs::symlink_metadata(self)?;
+        let metadata =
+            fs::symlink_metadata(self).map_err(|e| PathError::io("lstat", &self.0, e))?;
         let mut perms = metadata.permissions();
         perms.set_readonly(true);
-        fs::set_permissions(self.0.as_path(), perms)?;
+        fs::set_permissions(self.0.as_path(), perms)
+            .map_err(|e| PathError::io("set permissions on", &self.0, e))?;
         Ok(())
     }
 
     pub fn is_readonly(&self) -> Result<bool, PathError> {
-        Ok(self.0.symlink_metadata()?.permissions().readonly())
+        let metadata = self
+            .0
+            .symlink_metadata()
+            .map_err(|e| PathError::io("lstat", &self.0, e))?;
+        Ok(metadata.permissions().readonly())
     }
 
     pub fn create_with_contents(&self, contents: &str) -> Result<(), io::Error> {
@@ -233,11 +239,12 @@ impl AbsoluteSystemPathBuf {
     }
 
     pub fn open(&self) -> Result<fs::File, PathError> {
-        Ok(fs::File::open(&self.0)?)
+        fs::File::open(&self.0).map_err(|e| PathError::io("open", &self.0, e))
     }
 
     pub fn to_realpath(&self) -> Result<Self, PathError> {
-        let realpath = fs::canonicalize(&self.0)?;
+        let realpath =
+            fs::canonicalize(&self.0).map_err(|e| PathError::io("canonicalize", &self.0, e))?;
         Ok(Self(realpath))
     }
 
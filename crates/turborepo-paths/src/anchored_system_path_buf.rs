@@ -2,7 +2,9 @@ use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{AbsoluteSystemPath, IntoSystem, PathError, PathValidationError, RelativeUnixPathBuf};
+use crate::{
+    lexical, AbsoluteSystemPath, IntoSystem, PathError, PathValidationError, RelativeUnixPathBuf,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
 pub struct AnchoredSystemPathBuf(PathBuf);
@@ -27,13 +29,30 @@ impl AnchoredSystemPathBuf {
     ) -> Result<Self, PathError> {
         let root = root.as_ref();
         let path = path.as_ref();
-        let stripped_path = path
-            .as_path()
-            .strip_prefix(root.as_path())
-            .map_err(|_| PathValidationError::NotParent(root.to_string(), path.to_string()))?
-            .to_path_buf();
+        let not_parent = || PathValidationError::NotParent(root.to_string(), path.to_string());
 
-        Ok(AnchoredSystemPathBuf(stripped_path))
+        #[cfg(unix)]
+        {
+            use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+            let anchored = lexical::anchor_bytes(
+                root.as_path().as_os_str().as_bytes(),
+                path.as_path().as_os_str().as_bytes(),
+            )
+            .ok_or_else(not_parent)?;
+            Ok(AnchoredSystemPathBuf(OsStr::from_bytes(anchored).into()))
+        }
+        #[cfg(not(unix))]
+        {
+            use path_slash::{PathBufExt, PathExt};
+            let to_slash = |path: &AbsoluteSystemPath| {
+                path.as_path()
+                    .to_slash()
+                    .ok_or_else(|| PathValidationError::InvalidUnicode(path.as_path().to_owned()))
+            };
+            let (root_slash, path_slash) = (to_slash(root)?, to_slash(path)?);
+            let anchored = lexical::anchor(&root_slash, &path_slash).ok_or_else(not_parent)?;
+            Ok(AnchoredSystemPathBuf(PathBuf::from_slash(anchored)))
+        }
     }
 
     pub fn from_raw<P: AsRef<Path>>(raw: P) -> Result<Self, PathError> {
@@ -46,6 +65,26 @@ impl AnchoredSystemPathBuf {
         self.0.as_path()
     }
 
+    /// Iterates over this path and each of its parents, nearest first. Unlike
+    /// `Path::ancestors`, the anchor itself (the empty path) is not included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::path::Path;
+    ///
+    /// use turbopath::AnchoredSystemPathBuf;
+    /// let path = AnchoredSystemPathBuf::from_raw(Path::new("a").join("b")).unwrap();
+    /// let ancestors: Vec<_> = path.ancestors().collect();
+    /// assert_eq!(ancestors, vec![path.clone(), AnchoredSystemPathBuf::from_raw("a").unwrap()]);
+    /// ```
+    pub fn ancestors(&self) -> impl Iterator<Item = AnchoredSystemPathBuf> + '_ {
+        self.0
+            .ancestors()
+            .take_while(|ancestor| !ancestor.as_os_str().is_empty())
+            .map(|ancestor| AnchoredSystemPathBuf(ancestor.to_path_buf()))
+    }
+
     pub fn to_str(&self) -> Result<&str, PathError> {
         self.0
             .to_str()
@@ -0,0 +1,153 @@
+use std::fmt::Debug;
+
+use bstr::{BString, ByteSlice};
+
+use crate::{AnchoredSystemPathBuf, PathError, PathValidationError};
+
+/// A path relative to some anchor (usually the repository root) that always
+/// uses `/` as the separator, regardless of platform. This is the form paths
+/// take inside of cache artifacts.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct AnchoredUnixPathBuf(BString);
+
+impl AnchoredUnixPathBuf {
+    /// Validates that `path` stays under its anchor: it must be relative,
+    /// without a drive letter like `C:` that would make it absolute on
+    /// Windows, and without `..` segments. The bytes are otherwise kept as-is:
+    /// a `\` is an ordinary filename character in a unix path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use turbopath::AnchoredUnixPathBuf;
+    /// assert!(AnchoredUnixPathBuf::new("apps/web/.next").is_ok());
+    /// assert!(AnchoredUnixPathBuf::new("/apps/web").is_err());
+    /// assert!(AnchoredUnixPathBuf::new("C:/apps/web").is_err());
+    /// assert!(AnchoredUnixPathBuf::new("apps/../../web").is_err());
+    /// ```
+    pub fn new(path: impl Into<Vec<u8>>) -> Result<Self, PathError> {
+        let bytes: Vec<u8> = path.into();
+        let has_drive_letter =
+            matches!(bytes.as_slice(), [letter, b':', ..] if letter.is_ascii_alphabetic());
+        if bytes.first() == Some(&b'/') || has_drive_letter {
+            return Err(PathValidationError::not_relative_error(&bytes).into());
+        }
+        if bytes
+            .split(|&byte| byte == b'/')
+            .any(|segment| segment == b"..")
+        {
+            return Err(PathValidationError::EscapesAnchor(
+                String::from_utf8_lossy(&bytes).to_string(),
+            )
+            .into());
+        }
+        Ok(Self(BString::new(bytes)))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+
+    pub fn as_str(&self) -> Result<&str, PathError> {
+        self.0
+            .to_str()
+            .map_err(|_| PathError::Utf8Error(self.0.as_bytes().to_owned()))
+    }
+
+    /// Converts to a path using the system separator.
+    ///
+    /// On unix the bytes are already a system path, so this only fails if they
+    /// aren't UTF-8, like every other system path conversion. On Windows `\` is
+    /// a separator, so a segment containing one can't be represented and is
+    /// an error rather than being silently split into two segments.
+    pub fn into_system(self) -> Result<AnchoredSystemPathBuf, PathError> {
+        #[cfg(unix)]
+        {
+            use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+            AnchoredSystemPathBuf::try_from(Path::new(OsStr::from_bytes(self.0.as_bytes())))
+        }
+        #[cfg(not(unix))]
+        {
+            use std::path::Path;
+            let path = self.as_str()?;
+            if path.contains('\\') {
+                return Err(PathValidationError::NotSystem(path.to_string()).into());
+            }
+            AnchoredSystemPathBuf::try_from(Path::new(path))
+        }
+    }
+}
+
+impl Debug for AnchoredUnixPathBuf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.as_str() {
+            Ok(s) => write!(f, "{}", s),
+            Err(_) => write!(f, "Non-utf8 {:?}", self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+
+    use super::*;
+
+    #[test]
+    fn test_anchored_unix_path_buf_validation() {
+        for valid in ["", "foo", "foo/bar", "foo\\bar", "foo/..bar", "foo/C:/bar"] {
+            let path = AnchoredUnixPathBuf::new(valid).unwrap();
+            assert_eq!(path.as_str().unwrap(), valid);
+        }
+
+        for not_relative in ["/foo", "C:/foo", "c:foo"] {
+            assert_matches!(
+                AnchoredUnixPathBuf::new(not_relative),
+                Err(PathError::PathValidationError(
+                    PathValidationError::NotRelative(_)
+                ))
+            );
+        }
+        for escaping in ["..", "../foo", "foo/../../bar", "foo/.."] {
+            assert_matches!(
+                AnchoredUnixPathBuf::new(escaping),
+                Err(PathError::PathValidationError(
+                    PathValidationError::EscapesAnchor(_)
+                ))
+            );
+        }
+
+        let non_utf8 = AnchoredUnixPathBuf::new(b"foo/\xff".to_vec()).unwrap();
+        assert_matches!(non_utf8.as_str(), Err(PathError::Utf8Error(_)));
+    }
+
+    #[test]
+    fn test_into_system() {
+        let path = AnchoredUnixPathBuf::new("foo/bar/baz.txt").unwrap();
+        let expected = ["foo", "bar", "baz.txt"]
+            .iter()
+            .collect::<std::path::PathBuf>();
+        assert_eq!(path.into_system().unwrap().as_path(), expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_into_system_backslash_is_filename() {
+        let path = AnchoredUnixPathBuf::new("foo/bar\\baz").unwrap();
+        let system = path.into_system().unwrap();
+        assert_eq!(system.as_path().components().count(), 2);
+        assert_eq!(system.to_str().unwrap(), "foo/bar\\baz");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_into_system_rejects_unrepresentable() {
+        let path = AnchoredUnixPathBuf::new("foo/bar\\baz").unwrap();
+        assert_matches!(
+            path.into_system(),
+            Err(PathError::PathValidationError(
+                PathValidationError::NotSystem(_)
+            ))
+        );
+    }
+}
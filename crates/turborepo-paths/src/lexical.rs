@@ -0,0 +1,169 @@
+//! Lexical path manipulation that never touches the filesystem.
+//!
+//! Everything in this module operates on `/`-separated strings and only
+//! needs `alloc`, so it is available when turbopath is built without the
+//! `std` feature (e.g. for wasm targets with no filesystem). Paths are
+//! treated purely as text: symlinks are not resolved and nothing is checked
+//! for existence.
+
+use alloc::{string::String, vec::Vec};
+
+/// Normalizes a `/`-separated path by removing empty and `.` segments and
+/// resolving `..` against the preceding segment.
+///
+/// Returns `None` if the path would escape its starting point, i.e. a `..`
+/// segment has no preceding segment to cancel out. A leading `/` is kept.
+///
+/// # Examples
+///
+/// ```
+/// use turbopath::lexical::normalize;
+/// assert_eq!(normalize("a/./b//c/../d").as_deref(), Some("a/b/d"));
+/// assert_eq!(normalize("/a/b/..").as_deref(), Some("/a"));
+/// assert_eq!(normalize("a/../..").as_deref(), None);
+/// ```
+pub fn normalize(path: &str) -> Option<String> {
+    let is_absolute = path.starts_with('/');
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    let mut normalized = String::with_capacity(path.len());
+    if is_absolute {
+        normalized.push('/');
+    }
+    for (i, segment) in segments.iter().enumerate() {
+        if i > 0 {
+            normalized.push('/');
+        }
+        normalized.push_str(segment);
+    }
+    Some(normalized)
+}
+
+/// Joins `tail` onto `base`, inserting a separator if needed. If `tail` is
+/// absolute it replaces `base`, matching `std::path::Path::join`.
+///
+/// # Examples
+///
+/// ```
+/// use turbopath::lexical::join;
+/// assert_eq!(join("a/b", "c"), "a/b/c");
+/// assert_eq!(join("a/b/", "c"), "a/b/c");
+/// assert_eq!(join("", "c"), "c");
+/// assert_eq!(join("a/b", "/c"), "/c");
+/// ```
+pub fn join(base: &str, tail: &str) -> String {
+    String::from_utf8(join_bytes(base.as_bytes(), tail.as_bytes()))
+        .expect("joining two strings with a separator gives a string")
+}
+
+/// `join` for paths that aren't necessarily UTF-8, such as Unix paths
+/// stored as raw bytes.
+pub fn join_bytes(base: &[u8], tail: &[u8]) -> Vec<u8> {
+    if tail.starts_with(b"/") || base.is_empty() {
+        return tail.to_vec();
+    }
+    let mut joined = Vec::with_capacity(base.len() + 1 + tail.len());
+    joined.extend_from_slice(base);
+    if !base.ends_with(b"/") {
+        joined.push(b'/');
+    }
+    joined.extend_from_slice(tail);
+    joined
+}
+
+/// Returns `path` relative to `root`, or `None` if `root` is not an ancestor
+/// of `path`. Matching happens on whole segments, so `/a/bc` is not anchored
+/// at `/a/b`. Both paths are expected to be normalized.
+///
+/// # Examples
+///
+/// ```
+/// use turbopath::lexical::anchor;
+/// assert_eq!(anchor("/repo", "/repo/packages/ui"), Some("packages/ui"));
+/// assert_eq!(anchor("/repo", "/repo"), Some(""));
+/// assert_eq!(anchor("/repo", "/repository"), None);
+/// ```
+pub fn anchor<'a>(root: &str, path: &'a str) -> Option<&'a str> {
+    let anchored = anchor_bytes(root.as_bytes(), path.as_bytes())?;
+    // The anchored path is a suffix of `path` starting after a `/` or at
+    // either end, so it's on a character boundary
+    Some(&path[path.len() - anchored.len()..])
+}
+
+/// `anchor` for paths that aren't necessarily UTF-8, such as Unix paths
+/// stored as raw bytes.
+pub fn anchor_bytes<'a>(root: &[u8], path: &'a [u8]) -> Option<&'a [u8]> {
+    let root = match root.iter().rposition(|&byte| byte != b'/') {
+        Some(last) => &root[..=last],
+        None => &root[..0],
+    };
+    let rest = path.strip_prefix(root)?;
+    if rest.is_empty() || (root.is_empty() && !path.starts_with(b"/")) {
+        Some(rest)
+    } else {
+        rest.strip_prefix(b"/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let cases = [
+            ("", Some("")),
+            (".", Some("")),
+            ("a/b/c", Some("a/b/c")),
+            ("a//b/./c/", Some("a/b/c")),
+            ("a/b/../c", Some("a/c")),
+            ("a/..", Some("")),
+            ("..", None),
+            ("a/../../b", None),
+            ("/", Some("/")),
+            ("/a/../b", Some("/b")),
+            ("/..", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                normalize(input).as_deref(),
+                expected,
+                "normalize({input:?})"
+            );
+        }
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(join("a", "b/c"), "a/b/c");
+        assert_eq!(join("/", "a"), "/a");
+        assert_eq!(join("a", ""), "a/");
+        assert_eq!(join("a", "/b"), "/b");
+    }
+
+    #[test]
+    fn test_anchor() {
+        assert_eq!(anchor("/a/b", "/a/b/c/d"), Some("c/d"));
+        assert_eq!(anchor("/a/b/", "/a/b/c"), Some("c"));
+        assert_eq!(anchor("/a/b", "/a/bc"), None);
+        assert_eq!(anchor("/a/b", "/a"), None);
+        assert_eq!(anchor("", "a/b"), Some("a/b"));
+        assert_eq!(anchor("/", "/a"), Some("a"));
+    }
+
+    #[test]
+    fn test_non_utf8() {
+        assert_eq!(join_bytes(b"a\xff", b"b"), b"a\xff/b");
+        assert_eq!(anchor_bytes(b"/a\xff", b"/a\xff/b"), Some(&b"b"[..]));
+        assert_eq!(anchor_bytes(b"/a\xff", b"/a\xfe/b"), None);
+    }
+}
@@ -1,4 +1,7 @@
 #![feature(assert_matches)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
 /// Turborepo's path handling library
 /// Defines distinct path types for the different usecases of paths in turborepo
@@ -12,6 +15,8 @@
 /// - `AnchoredSystemPath(Buf)`: a path that is relative to a specific directory
 ///   and uses the system's path separator. Used for handling files relative to
 ///   the repository root.
+/// - `AnchoredUnixPathBuf`: a path that is relative to a specific directory and
+///   uses the unix path separator. Used for paths inside cache artifacts.
 ///
 /// As in `std::path`, there are `Path` and `PathBuf` variants of each path
 /// type, that indicate whether the path is borrowed or owned.
@@ -26,53 +31,139 @@
 /// The only case where initializing a path type without validation is
 /// recommended is inside turbopath itself. But that unchecked initialization
 /// should be considered unsafe
+///
+/// The path types above require the `std` feature (enabled by default).
+/// Without it, only the string-based helpers in `lexical` are available.
+#[cfg(feature = "std")]
 mod absolute_system_path;
+#[cfg(feature = "std")]
 mod absolute_system_path_buf;
+#[cfg(feature = "std")]
 mod anchored_system_path_buf;
+#[cfg(feature = "std")]
+mod anchored_unix_path_buf;
+pub mod lexical;
+#[cfg(feature = "std")]
 mod relative_system_path_buf;
+#[cfg(feature = "std")]
 mod relative_unix_path;
+#[cfg(feature = "std")]
 mod relative_unix_path_buf;
 
+#[cfg(feature = "std")]
 use std::{
     io,
     path::{Path, PathBuf, StripPrefixError},
 };
 
+#[cfg(feature = "std")]
 pub use absolute_system_path::AbsoluteSystemPath;
+#[cfg(feature = "std")]
 pub use absolute_system_path_buf::AbsoluteSystemPathBuf;
+#[cfg(feature = "std")]
 pub use anchored_system_path_buf::AnchoredSystemPathBuf;
+#[cfg(feature = "std")]
+pub use anchored_unix_path_buf::AnchoredUnixPathBuf;
+#[cfg(feature = "std")]
 use path_slash::{PathBufExt, PathExt};
+#[cfg(feature = "std")]
 pub use relative_system_path_buf::RelativeSystemPathBuf;
+#[cfg(feature = "std")]
 pub use relative_unix_path::RelativeUnixPath;
+#[cfg(feature = "std")]
 pub use relative_unix_path_buf::RelativeUnixPathBuf;
 
+#[cfg(feature = "std")]
 #[derive(Debug, thiserror::Error)]
 pub enum PathError {
     #[error("Path validation failed: {0}")]
     PathValidationError(#[from] PathValidationError),
-    #[error("IO Error {0}")]
-    IO(#[from] io::Error),
+    #[error("failed to {operation}{}: {source}", display_io_path(path))]
+    IO {
+        operation: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
     #[error("Path prefix error: {0}")]
     PrefixError(#[from] StripPrefixError),
     #[error("Invalid UTF8: {0:?}")]
     Utf8Error(Vec<u8>),
 }
 
+// An empty path means the path isn't known, see `From<io::Error>`
+#[cfg(feature = "std")]
+fn display_io_path(path: &Path) -> String {
+    if path.as_os_str().is_empty() {
+        String::new()
+    } else {
+        format!(" {}", path.display())
+    }
+}
+
+/// For callers that only have an `io::Error`. Neither the path nor the
+/// operation is known, so prefer building `PathError::IO` with both.
+#[cfg(feature = "std")]
+impl From<io::Error> for PathError {
+    fn from(source: io::Error) -> Self {
+        PathError::io("perform I/O", PathBuf::new(), source)
+    }
+}
+
+#[cfg(feature = "std")]
 impl From<std::string::FromUtf8Error> for PathError {
     fn from(value: std::string::FromUtf8Error) -> Self {
         PathError::Utf8Error(value.into_bytes())
     }
 }
 
+#[cfg(feature = "std")]
 impl PathError {
+    pub(crate) fn io(operation: &'static str, path: impl Into<PathBuf>, source: io::Error) -> Self {
+        PathError::IO {
+            operation,
+            path: path.into(),
+            source,
+        }
+    }
+
     pub fn is_io_error(&self, kind: io::ErrorKind) -> bool {
         match self {
-            PathError::IO(err) => err.kind() == kind,
+            PathError::IO { source, .. } => source.kind() == kind,
             _ => false,
         }
     }
+
+    pub fn is_not_found(&self) -> bool {
+        self.is_io_error(io::ErrorKind::NotFound)
+    }
+
+    pub fn is_permission_denied(&self) -> bool {
+        self.is_io_error(io::ErrorKind::PermissionDenied)
+    }
+
+    /// The path involved in the failed operation, if it is known
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            PathError::IO { path, .. } if path.as_os_str().is_empty() => None,
+            PathError::IO { path, .. } => Some(path),
+            PathError::PathValidationError(
+                PathValidationError::InvalidUnicode(path) | PathValidationError::NotAbsolute(path),
+            ) => Some(path),
+            PathError::PathValidationError(
+                PathValidationError::NotRelative(path)
+                | PathValidationError::NotParent(_, path)
+                | PathValidationError::EscapesAnchor(path)
+                | PathValidationError::NotUnix(path)
+                | PathValidationError::NotSystem(path)
+                | PathValidationError::PrefixError(_, path),
+            ) => Some(Path::new(path)),
+            PathError::PrefixError(_) | PathError::Utf8Error(_) => None,
+        }
+    }
 }
 
+#[cfg(feature = "std")]
 // Custom error type for path validation errors
 #[derive(Debug, thiserror::Error)]
 pub enum PathValidationError {
@@ -84,6 +175,8 @@ pub enum PathValidationError {
     NotRelative(String),
     #[error("Path {0} is not parent of {1}")]
     NotParent(String, String),
+    #[error("Path {0} leaves its anchor")]
+    EscapesAnchor(String),
     #[error("Path {0} is not a unix path")]
     NotUnix(String),
     #[error("Path {0} is not a system path")]
@@ -92,6 +185,7 @@ pub enum PathValidationError {
     PrefixError(String, String),
 }
 
+#[cfg(feature = "std")]
 impl PathValidationError {
     pub(crate) fn not_relative_error(bytes: &[u8]) -> PathValidationError {
         let s = String::from_utf8_lossy(bytes).to_string();
@@ -99,14 +193,17 @@ impl PathValidationError {
     }
 }
 
+#[cfg(feature = "std")]
 trait IntoSystem {
     fn into_system(self) -> Result<PathBuf, PathValidationError>;
 }
 
+#[cfg(feature = "std")]
 trait IntoUnix {
     fn into_unix(self) -> Result<PathBuf, PathValidationError>;
 }
 
+#[cfg(feature = "std")]
 impl IntoSystem for &Path {
     fn into_system(self) -> Result<PathBuf, PathValidationError> {
         let path_str = self
@@ -117,6 +214,7 @@ impl IntoSystem for &Path {
     }
 }
 
+#[cfg(feature = "std")]
 impl IntoUnix for &Path {
     /// NOTE: `into_unix` *only* converts Windows paths to Unix paths *on* a
     /// Windows system. Do not pass a Windows path on a Unix system and
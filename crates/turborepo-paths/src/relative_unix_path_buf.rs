@@ -1,8 +1,11 @@
-use std::{fmt::Debug, io::Write};
+use std::{
+    fmt::Debug,
+    io::{self, Write},
+};
 
 use bstr::{BString, ByteSlice};
 
-use crate::{PathError, PathValidationError};
+use crate::{lexical, PathError, PathValidationError};
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 pub struct RelativeUnixPathBuf(BString);
@@ -28,6 +31,11 @@ impl RelativeUnixPathBuf {
     // "<escaped path>", where escaped_path is the path with '"' and '\n'
     // characters escaped with '\'.
     pub fn write_escaped_bytes<W: Write>(&self, writer: &mut W) -> Result<(), PathError> {
+        self.write_escaped_bytes_inner(writer)
+            .map_err(|e| PathError::io("write", self.0.to_str_lossy().into_owned(), e))
+    }
+
+    fn write_escaped_bytes_inner<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
         writer.write_all(&[b'\"'])?;
         // i is our pointer into self.0, and to_escape_index is a pointer to the next
         // byte to be escaped. Each time we find a byte to be escaped, we write
@@ -53,44 +61,23 @@ impl RelativeUnixPathBuf {
     }
 
     pub fn strip_prefix(&self, prefix: &RelativeUnixPathBuf) -> Result<Self, PathError> {
-        let prefix_len = prefix.0.len();
-        if prefix_len == 0 {
-            return Ok(self.clone());
-        }
-        if !self.0.starts_with(&prefix.0) {
-            return Err(PathError::PathValidationError(
+        match lexical::anchor_bytes(&prefix.0, &self.0) {
+            Some(tail) => Self::new(tail),
+            // The prefix matches, but not on a segment boundary
+            None if self.0.starts_with(&prefix.0) => Err(PathError::PathValidationError(
+                PathValidationError::PrefixError(
+                    prefix.0.to_str_lossy().into_owned(),
+                    self.0.to_str_lossy().into_owned(),
+                ),
+            )),
+            None => Err(PathError::PathValidationError(
                 PathValidationError::NotParent(prefix.0.to_string(), self.0.to_string()),
-            ));
-        }
-
-        // Handle the case where we are stripping the entire contents of this path
-        if self.0.len() == prefix.0.len() {
-            return Self::new("");
+            )),
         }
-
-        // We now know that this path starts with the prefix, and that this path's
-        // length is greater than the prefix's length
-        if self.0[prefix_len] != b'/' {
-            let prefix_str = prefix.0.to_str_lossy().into_owned();
-            let this = self.0.to_str_lossy().into_owned();
-            return Err(PathError::PathValidationError(
-                PathValidationError::PrefixError(prefix_str, this),
-            ));
-        }
-
-        let tail_slice = &self.0[(prefix_len + 1)..];
-        Self::new(tail_slice)
     }
 
     pub fn join(&self, tail: &RelativeUnixPathBuf) -> Self {
-        let buffer = Vec::with_capacity(self.0.len() + 1 + tail.0.len());
-        let mut path = BString::new(buffer);
-        if self.0.len() > 0 {
-            path.extend_from_slice(&self.0);
-            path.push(b'/');
-        }
-        path.extend_from_slice(&tail.0);
-        Self(path)
+        Self(BString::new(lexical::join_bytes(&self.0, &tail.0)))
     }
 }
 
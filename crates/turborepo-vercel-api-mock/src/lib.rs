@@ -36,7 +36,9 @@ pub async fn start_test_server(port: u16) -> Result<()> {
                         email: EXPECTED_EMAIL.to_string(),
                         name: None,
                         created_at: EXPECTED_USER_CREATED_AT,
+                        extra: Default::default(),
                     },
+                    extra: Default::default(),
                 })
             }),
         )
@@ -51,7 +53,9 @@ pub async fn start_test_server(port: u16) -> Result<()> {
                         created_at: EXPECTED_TEAM_CREATED_AT,
                         created: Default::default(),
                         membership: Membership::new(Role::Owner),
+                        extra: Default::default(),
                     }],
+                    extra: Default::default(),
                 })
             }),
         )
@@ -62,7 +66,9 @@ pub async fn start_test_server(port: u16) -> Result<()> {
                     spaces: vec![Space {
                         id: EXPECTED_SPACE_ID.to_string(),
                         name: EXPECTED_SPACE_NAME.to_string(),
+                        extra: Default::default(),
                     }],
+                    extra: Default::default(),
                 })
             }),
         )
@@ -71,6 +77,7 @@ pub async fn start_test_server(port: u16) -> Result<()> {
             get(|| async {
                 Json(CachingStatusResponse {
                     status: CachingStatus::Enabled,
+                    extra: Default::default(),
                 })
             }),
         )
@@ -80,6 +87,7 @@ pub async fn start_test_server(port: u16) -> Result<()> {
                 Json(VerificationResponse {
                     token: EXPECTED_TOKEN.to_string(),
                     team_id: Some(EXPECTED_SSO_TEAM_ID.to_string()),
+                    extra: Default::default(),
                 })
             }),
         );